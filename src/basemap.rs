@@ -0,0 +1,93 @@
+//! Module for the base map images the maps are drawn on
+//!
+//! Each base map is calibrated with a [`Projection`] in the config that converts world
+//! coordinates to pixels on its image, so new maps can be added without touching the code.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use image::io::Reader as ImageReader;
+use image::{ImageBuffer, Rgba};
+use once_cell::sync::Lazy;
+use serde::Deserialize;
+
+use crate::Error;
+
+/// Images that have already been loaded keyed by their path
+static IMAGES: Lazy<Mutex<HashMap<String, Arc<ImageBuffer<Rgba<u8>, Vec<u8>>>>>> =
+    Lazy::new(Default::default);
+
+/// Linear mapping between world coordinates and the pixels of a base map
+#[derive(Deserialize, Clone, Copy, PartialEq)]
+pub struct Projection {
+    /// Number of blocks per pixel
+    pub scale: f64,
+    /// Pixel x coordinate of world x 0
+    pub offset_x: f64,
+    /// Pixel y coordinate of world z 0
+    pub offset_z: f64,
+}
+
+impl Default for Projection {
+    /// Calibration of the main map that comes with the bot
+    fn default() -> Self {
+        Self {
+            scale: 3.0,
+            offset_x: 1364.0 - 566.333,
+            offset_z: 2162.0 + 41.0,
+        }
+    }
+}
+
+impl Projection {
+    /// Pixel x coordinate of a world x coordinate
+    pub fn x(&self, x: f64) -> f64 {
+        x / self.scale + self.offset_x
+    }
+
+    /// Pixel y coordinate of a world z coordinate
+    pub fn z(&self, z: f64) -> f64 {
+        z / self.scale + self.offset_z
+    }
+
+    /// Pixel coordinates of a point in the world given as `(x, z)`
+    pub fn to_pixel(&self, (x, z): (f64, f64)) -> (f64, f64) {
+        (self.x(x), self.z(z))
+    }
+
+    /// World coordinates of a pixel as `(x, z)`
+    pub fn to_world(&self, (px, py): (f64, f64)) -> (f64, f64) {
+        (
+            (px - self.offset_x) * self.scale,
+            (py - self.offset_z) * self.scale,
+        )
+    }
+
+    /// Length in pixels of a distance in blocks
+    pub fn length(&self, blocks: f64) -> f64 {
+        blocks / self.scale
+    }
+}
+
+/// Load an image from disk, every image is only read once
+pub fn load_image(path: &str) -> Result<Arc<ImageBuffer<Rgba<u8>, Vec<u8>>>, Error> {
+    if let Some(img) = IMAGES.lock().unwrap().get(path) {
+        return Ok(img.clone());
+    }
+
+    let img = Arc::new(ImageReader::open(path)?.decode()?.into_rgba8());
+    IMAGES.lock().unwrap().insert(path.to_string(), img.clone());
+
+    Ok(img)
+}
+
+/// Width and height of an image, only the header is read if it hasn't been loaded yet
+pub async fn image_dimensions(path: &str) -> Result<(u32, u32), Error> {
+    let loaded = IMAGES.lock().unwrap().get(path).map(|img| img.dimensions());
+    if let Some(dimensions) = loaded {
+        return Ok(dimensions);
+    }
+
+    let path = path.to_string();
+    Ok(tokio::task::spawn_blocking(move || image::image_dimensions(path)).await??)
+}
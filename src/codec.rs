@@ -0,0 +1,765 @@
+//! Module for decoding item strings shared by wynntils
+//!
+//! Wynntils has used a few different formats for sharing items in chat. [`decode`] detects which
+//! one a pasted string uses and turns it into a [`DecodedItem`] that can be rendered.
+
+use std::collections::BTreeMap;
+use std::fmt;
+
+use crate::wynn::items::{AttackSpeed, Identification, Powders, Type};
+
+/// Start of a legacy chat item
+pub const START_CHAR: char = '󵿰';
+/// End of a legacy chat item
+pub const END_CHAR: char = '󵿱';
+/// Separator between the sections of a legacy chat item
+pub const SEPARATOR: char = '󵿲';
+/// Offset of the characters used for encoding numbers in the legacy format
+pub const OFFSET: i32 = 0xF5000;
+
+/// Errors that can occur while decoding an item string
+#[derive(Debug)]
+pub enum DecodeError {
+    /// The string isn't in any format we know about
+    UnknownFormat,
+    /// The string uses a newer version of the encoding than we support
+    UnsupportedVersion(u8),
+    /// The string ended before all of the data could be read
+    UnexpectedEnd,
+    /// The string contains data that doesn't make sense
+    Invalid(&'static str),
+}
+
+impl fmt::Display for DecodeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodeError::UnknownFormat => write!(f, "the given string is not a wynntils item"),
+            DecodeError::UnsupportedVersion(v) => {
+                write!(f, "the item uses an unsupported encoding version ({})", v)
+            }
+            DecodeError::UnexpectedEnd => write!(f, "the given string is incomplete"),
+            DecodeError::Invalid(why) => write!(f, "the given string is invalid: {}", why),
+        }
+    }
+}
+
+impl std::error::Error for DecodeError {}
+
+/// An item decoded from an item string
+pub enum DecodedItem {
+    /// A normal item that can be found in the item database
+    Gear(GearItem),
+    /// A crafted item which carries all of its stats in the string itself
+    Crafted(CraftedItem),
+}
+
+/// A gear item, the stats of these are looked up from the item database by name
+pub struct GearItem {
+    pub name: String,
+    pub ids: GearIds,
+    pub powders: Vec<Powders>,
+    pub rerolls: i32,
+}
+
+/// The identifications of a gear item
+pub enum GearIds {
+    /// Legacy chat format, one encoded value for every identification in identification order
+    Legacy(Vec<i32>),
+    /// Roll of every identification as a percentage of the base value
+    Rolls(BTreeMap<Identification, i32>),
+}
+
+/// What kind of item a crafted item is
+pub enum CraftedKind {
+    Gear(Type),
+    Potion,
+    Food,
+    Scroll,
+}
+
+/// A crafted item
+pub struct CraftedItem {
+    pub name: String,
+    pub kind: CraftedKind,
+    pub attack_speed: Option<AttackSpeed>,
+    pub level: i32,
+    /// Skill point requirements in the order str, dex, int, def, agi
+    pub skills: [i32; 5],
+    /// Damages as min and max in the order neutral, earth, thunder, water, fire, air
+    pub damages: [Option<(i32, i32)>; 6],
+    pub health: Option<i32>,
+    /// Defences in the order earth, thunder, water, fire, air
+    pub defences: [Option<i32>; 5],
+    /// Identifications and their min and max values
+    pub ids: BTreeMap<Identification, (i32, i32)>,
+    pub powders: Vec<Powders>,
+    pub powder_slots: u8,
+    /// Current and max durability
+    pub durability: Option<(i32, i32)>,
+    /// Current and max uses
+    pub uses: Option<(i32, i32)>,
+}
+
+/// Detect the format of an item string and decode it
+pub fn decode(input: &str) -> Result<DecodedItem, DecodeError> {
+    let input = input.trim();
+
+    if input.starts_with(START_CHAR) {
+        legacy::decode(input)
+    } else if input.chars().next().map_or(false, is_encoded_char) {
+        v1::decode(input)
+    } else if input.contains(SEPARATOR) {
+        // chat sometimes eats the start of legacy items, which were accepted without it before
+        legacy::decode(input)
+    } else {
+        Err(DecodeError::UnknownFormat)
+    }
+}
+
+/// Checks if the char is one used by the newer encoding
+fn is_encoded_char(c: char) -> bool {
+    (0xF0000..=0x10FFFF).contains(&(c as u32))
+}
+
+/// The original wynntils chat item format
+///
+/// https://github.com/Wynntils/Wynntils/blob/development/src/main/java/com/wynntils/modules/utilities/managers/ChatItemManager.java
+pub mod legacy {
+    use super::*;
+
+    pub fn decode(input: &str) -> Result<DecodedItem, DecodeError> {
+        let mut temp = input
+            .trim_start_matches(START_CHAR)
+            .trim_end_matches(END_CHAR)
+            .split_terminator(SEPARATOR);
+
+        let name = temp.next().ok_or(DecodeError::UnexpectedEnd)?.to_string();
+        let ids = temp.next().ok_or(DecodeError::UnexpectedEnd)?;
+        let powders = temp.next();
+
+        // Read rerolls either from the id section or the powder section
+        let rerolls = powders
+            .unwrap_or(ids)
+            .chars()
+            .last()
+            .ok_or(DecodeError::UnexpectedEnd)? as i32
+            - OFFSET;
+
+        // Parse powders
+        let mut parsedpowders = Vec::new();
+        if let Some(powders) = powders {
+            let mut powders: Vec<i32> = powders.chars().map(|c| c as i32 - OFFSET).collect();
+            // Remove the reroll char from powder chars
+            powders.pop();
+            powders.reverse();
+
+            for mut p in powders.into_iter() {
+                while p > 0 {
+                    parsedpowders.push(Powders::from_i32(p % 6 - 1));
+
+                    p /= 6;
+                }
+            }
+        }
+        parsedpowders.reverse();
+
+        Ok(DecodedItem::Gear(GearItem {
+            name,
+            ids: GearIds::Legacy(ids.chars().map(|c| c as i32 - OFFSET).collect()),
+            powders: parsedpowders,
+            rerolls,
+        }))
+    }
+}
+
+/// The newer wynntils item encoding
+///
+/// The data is a list of blocks each starting with a block id, these bytes are then packed two at
+/// a time into characters in the unicode private use areas.
+pub mod v1 {
+    use super::*;
+
+    /// Version byte for the only version of the encoding that we know
+    const VERSION: u8 = 0;
+
+    const START_BLOCK: u8 = 0;
+    const TYPE_BLOCK: u8 = 1;
+    const NAME_BLOCK: u8 = 2;
+    const IDENTIFICATION_BLOCK: u8 = 3;
+    const POWDER_BLOCK: u8 = 4;
+    const REROLL_BLOCK: u8 = 5;
+    const SHINY_BLOCK: u8 = 6;
+    const GEAR_TYPE_BLOCK: u8 = 7;
+    const DURABILITY_BLOCK: u8 = 8;
+    const REQUIREMENTS_BLOCK: u8 = 9;
+    const DAMAGE_BLOCK: u8 = 10;
+    const DEFENCE_BLOCK: u8 = 11;
+    const CUSTOM_ID_BLOCK: u8 = 12;
+    const CONSUMABLE_TYPE_BLOCK: u8 = 13;
+    const USES_BLOCK: u8 = 14;
+    const EFFECTS_BLOCK: u8 = 15;
+    const END_BLOCK: u8 = 255;
+
+    /// Item types that the type block can contain
+    const ITEM_GEAR: u8 = 0;
+    const ITEM_TOME: u8 = 1;
+    const ITEM_CHARM: u8 = 2;
+    const ITEM_CRAFTED_GEAR: u8 = 3;
+    const ITEM_CRAFTED_CONSUMABLE: u8 = 4;
+
+    /// Identifications by the id they have in the encoding
+    ///
+    /// The position in this table is the byte on the wire, so it must never be reordered and new
+    /// identifications get appended at the end.
+    const IDENTIFICATIONS: [Identification; 50] = [
+        Identification::rawStrength,
+        Identification::rawDexterity,
+        Identification::rawIntelligence,
+        Identification::rawDefence,
+        Identification::rawAgility,
+        Identification::attackSpeed,
+        Identification::rawMainAttackNeutralDamage,
+        Identification::mainAttackDamage,
+        Identification::rawNeutralSpellDamage,
+        Identification::rawSpellDamage,
+        Identification::spellDamage,
+        Identification::rawHealth,
+        Identification::rawHealthRegen,
+        Identification::healthRegen,
+        Identification::lifeSteal,
+        Identification::manaRegen,
+        Identification::manaSteal,
+        Identification::earthDamage,
+        Identification::thunderDamage,
+        Identification::waterDamage,
+        Identification::fireDamage,
+        Identification::airDamage,
+        Identification::earthDefence,
+        Identification::thunderDefence,
+        Identification::waterDefence,
+        Identification::fireDefence,
+        Identification::airDefence,
+        Identification::exploding,
+        Identification::poison,
+        Identification::thorns,
+        Identification::reflection,
+        Identification::walkSpeed,
+        Identification::sprint,
+        Identification::sprintRegen,
+        Identification::rawJumpHeight,
+        Identification::soulPointRegen,
+        Identification::lootBonus,
+        Identification::lootQuality,
+        Identification::emeraldStealing,
+        Identification::xpBonus,
+        Identification::gatherXPBonus,
+        Identification::gatherSpeed,
+        Identification::raw1stSpellCost,
+        Identification::SpellCost1,
+        Identification::raw2ndSpellCost,
+        Identification::SpellCost2,
+        Identification::raw3rdSpellCost,
+        Identification::SpellCost3,
+        Identification::raw4thSpellCost,
+        Identification::SpellCost4,
+    ];
+
+    pub fn decode(input: &str) -> Result<DecodedItem, DecodeError> {
+        let bytes = to_bytes(input)?;
+        let mut r = Reader {
+            data: &bytes,
+            pos: 0,
+        };
+
+        if r.u8()? != START_BLOCK {
+            return Err(DecodeError::Invalid("missing start block"));
+        }
+        let version = r.u8()?;
+        if version != VERSION {
+            return Err(DecodeError::UnsupportedVersion(version));
+        }
+
+        let mut itemtype = None;
+        let mut name = None;
+        let mut rolls = BTreeMap::new();
+        let mut powders = Vec::new();
+        let mut powder_slots = 0;
+        let mut rerolls = 0;
+        let mut kind = None;
+        let mut durability = None;
+        let mut level = 0;
+        let mut skills = [0; 5];
+        let mut attack_speed = None;
+        let mut damages = [None; 6];
+        let mut health = None;
+        let mut defences = [None; 5];
+        let mut custom_ids = BTreeMap::new();
+        let mut uses = None;
+
+        loop {
+            match r.u8()? {
+                TYPE_BLOCK => itemtype = Some(r.u8()?),
+                NAME_BLOCK => name = Some(r.string()?),
+                IDENTIFICATION_BLOCK => {
+                    let count = r.u8()?;
+                    let extended = r.u8()? != 0;
+                    for _ in 0..count {
+                        let id = r.identification()?;
+                        // extended data also contains the base value which we get from the item db
+                        if extended {
+                            r.varint()?;
+                        }
+                        rolls.insert(id, r.u8()? as i32);
+                    }
+                }
+                POWDER_BLOCK => {
+                    powder_slots = r.u8()?;
+                    let count = r.u8()?;
+                    for _ in 0..count {
+                        let element = r.u8()?;
+                        // powder tier
+                        r.u8()?;
+                        powders.push(Powders::from_i32(element as i32));
+                    }
+                }
+                REROLL_BLOCK => rerolls = r.u8()? as i32,
+                SHINY_BLOCK => {
+                    r.u8()?;
+                    r.varint()?;
+                }
+                GEAR_TYPE_BLOCK => kind = Some(CraftedKind::Gear(gear_type(r.u8()?)?)),
+                DURABILITY_BLOCK => {
+                    // effectiveness
+                    r.u8()?;
+                    let max = r.varint()?;
+                    let current = r.varint()?;
+                    durability = Some((current, max));
+                }
+                REQUIREMENTS_BLOCK => {
+                    level = r.u8()? as i32;
+                    // class requirement
+                    r.u8()?;
+                    let count = r.u8()?;
+                    for _ in 0..count {
+                        let skill = r.u8()? as usize;
+                        let value = r.varint()?;
+                        *skills
+                            .get_mut(skill)
+                            .ok_or(DecodeError::Invalid("unknown skill"))? = value;
+                    }
+                }
+                DAMAGE_BLOCK => {
+                    attack_speed = Some(attack_speed_from(r.u8()?)?);
+                    let count = r.u8()?;
+                    for _ in 0..count {
+                        // damage type 0 is "all" which crafted items never have
+                        let dtype = (r.u8()? as usize).checked_sub(1);
+                        let min = r.varint()?;
+                        let max = r.varint()?;
+                        *dtype
+                            .and_then(|t| damages.get_mut(t))
+                            .ok_or(DecodeError::Invalid("unknown damage type"))? = Some((min, max));
+                    }
+                }
+                DEFENCE_BLOCK => {
+                    health = Some(r.varint()?);
+                    let count = r.u8()?;
+                    for _ in 0..count {
+                        let element = r.u8()? as usize;
+                        let value = r.varint()?;
+                        *defences
+                            .get_mut(element)
+                            .ok_or(DecodeError::Invalid("unknown element"))? = Some(value);
+                    }
+                }
+                CUSTOM_ID_BLOCK => {
+                    let count = r.u8()?;
+                    for _ in 0..count {
+                        let id = r.identification()?;
+                        let min = r.varint()?;
+                        let max = r.varint()?;
+                        custom_ids.insert(id, (min, max));
+                    }
+                }
+                CONSUMABLE_TYPE_BLOCK => {
+                    kind = Some(match r.u8()? {
+                        0 => CraftedKind::Potion,
+                        1 => CraftedKind::Food,
+                        2 => CraftedKind::Scroll,
+                        _ => return Err(DecodeError::Invalid("unknown consumable type")),
+                    })
+                }
+                USES_BLOCK => {
+                    let current = r.u8()? as i32;
+                    let max = r.u8()? as i32;
+                    uses = Some((current, max));
+                }
+                EFFECTS_BLOCK => {
+                    let count = r.u8()?;
+                    for _ in 0..count {
+                        r.u8()?;
+                        r.varint()?;
+                    }
+                }
+                END_BLOCK => break,
+                _ => return Err(DecodeError::Invalid("unknown data block")),
+            }
+        }
+
+        let name = name.ok_or(DecodeError::Invalid("missing item name"))?;
+
+        match itemtype.ok_or(DecodeError::Invalid("missing item type"))? {
+            ITEM_GEAR | ITEM_TOME | ITEM_CHARM => Ok(DecodedItem::Gear(GearItem {
+                name,
+                ids: GearIds::Rolls(rolls),
+                powders,
+                rerolls,
+            })),
+            ITEM_CRAFTED_GEAR | ITEM_CRAFTED_CONSUMABLE => Ok(DecodedItem::Crafted(CraftedItem {
+                name,
+                kind: kind.ok_or(DecodeError::Invalid("missing crafted item type"))?,
+                attack_speed,
+                level,
+                skills,
+                damages,
+                health,
+                defences,
+                ids: custom_ids,
+                powders,
+                powder_slots,
+                durability,
+                uses,
+            })),
+            _ => Err(DecodeError::Invalid("unknown item type")),
+        }
+    }
+
+    /// Unpack the characters of the string into bytes
+    ///
+    /// Two bytes are stored per character in the supplementary private use area A, the two values
+    /// that don't fit there go to area B and a single trailing byte is stored at the end of area B.
+    fn to_bytes(input: &str) -> Result<Vec<u8>, DecodeError> {
+        let mut out = Vec::new();
+
+        for c in input.chars() {
+            match c as u32 {
+                cp @ 0xF0000..=0xFFFFD => {
+                    let v = cp - 0xF0000;
+                    out.push((v >> 8) as u8);
+                    out.push(v as u8);
+                }
+                cp @ 0x100000..=0x100001 => {
+                    out.push(0xFF);
+                    out.push((cp - 0x100000 + 0xFE) as u8);
+                }
+                cp @ 0x10FF00..=0x10FFFF => out.push((cp - 0x10FF00) as u8),
+                _ => return Err(DecodeError::Invalid("unexpected character")),
+            }
+        }
+
+        Ok(out)
+    }
+
+    fn gear_type(n: u8) -> Result<Type, DecodeError> {
+        Ok(match n {
+            0 => Type::SPEAR,
+            1 => Type::WAND,
+            2 => Type::BOW,
+            3 => Type::DAGGER,
+            4 => Type::RELIK,
+            5 => Type::HELMET,
+            6 => Type::CHESTPLATE,
+            7 => Type::LEGGINGS,
+            8 => Type::BOOTS,
+            9 => Type::RING,
+            10 => Type::BRACELET,
+            11 => Type::NECKLACE,
+            _ => return Err(DecodeError::Invalid("unknown gear type")),
+        })
+    }
+
+    fn attack_speed_from(n: u8) -> Result<AttackSpeed, DecodeError> {
+        Ok(match n {
+            0 => AttackSpeed::SUPER_SLOW,
+            1 => AttackSpeed::VERY_SLOW,
+            2 => AttackSpeed::SLOW,
+            3 => AttackSpeed::NORMAL,
+            4 => AttackSpeed::FAST,
+            5 => AttackSpeed::VERY_FAST,
+            6 => AttackSpeed::SUPER_FAST,
+            _ => return Err(DecodeError::Invalid("unknown attack speed")),
+        })
+    }
+
+    /// Helper for reading values out of the decoded bytes
+    struct Reader<'a> {
+        data: &'a [u8],
+        pos: usize,
+    }
+
+    impl<'a> Reader<'a> {
+        fn u8(&mut self) -> Result<u8, DecodeError> {
+            let b = *self.data.get(self.pos).ok_or(DecodeError::UnexpectedEnd)?;
+            self.pos += 1;
+            Ok(b)
+        }
+
+        /// Reads a zigzag encoded variable length integer
+        fn varint(&mut self) -> Result<i32, DecodeError> {
+            let mut value: u64 = 0;
+            let mut shift = 0;
+            loop {
+                let b = self.u8()?;
+                value |= ((b & 0x7F) as u64) << shift;
+                if b & 0x80 == 0 {
+                    break;
+                }
+                shift += 7;
+                if shift > 35 {
+                    return Err(DecodeError::Invalid("number too large"));
+                }
+            }
+            Ok(((value >> 1) as i64 ^ -((value & 1) as i64)) as i32)
+        }
+
+        /// Reads a null terminated utf-8 string
+        fn string(&mut self) -> Result<String, DecodeError> {
+            let rest = &self.data[self.pos..];
+            let len = rest
+                .iter()
+                .position(|b| *b == 0)
+                .ok_or(DecodeError::UnexpectedEnd)?;
+            let s = String::from_utf8(rest[..len].to_vec())
+                .map_err(|_| DecodeError::Invalid("item name is not valid utf-8"))?;
+            self.pos += len + 1;
+            Ok(s)
+        }
+
+        fn identification(&mut self) -> Result<Identification, DecodeError> {
+            IDENTIFICATIONS
+                .get(self.u8()? as usize)
+                .copied()
+                .ok_or(DecodeError::Invalid("unknown identification"))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Build a legacy chat item from its parts
+    fn legacy_string(name: &str, ids: &[i32], powders: &[i32], rerolls: i32) -> String {
+        let chars = |values: &[i32]| -> String {
+            values
+                .iter()
+                .map(|v| char::from_u32((v + OFFSET) as u32).unwrap())
+                .collect()
+        };
+
+        let mut out = String::new();
+        out.push(START_CHAR);
+        out.push_str(name);
+        out.push(SEPARATOR);
+        out.push_str(&chars(ids));
+        out.push(SEPARATOR);
+        out.push_str(&chars(powders));
+        out.push_str(&chars(&[rerolls]));
+        out.push(END_CHAR);
+        out
+    }
+
+    /// Pack bytes into characters the same way wynntils does
+    fn v1_string(bytes: &[u8]) -> String {
+        bytes
+            .chunks(2)
+            .map(|pair| {
+                let cp = match *pair {
+                    [0xFF, b @ 0xFE..=0xFF] => 0x100000 + (b - 0xFE) as u32,
+                    [a, b] => 0xF0000 + ((a as u32) << 8 | b as u32),
+                    [a] => 0x10FF00 + a as u32,
+                    _ => unreachable!(),
+                };
+                char::from_u32(cp).unwrap()
+            })
+            .collect()
+    }
+
+    /// Zigzag encoded variable length integer
+    fn varint(n: i32) -> Vec<u8> {
+        let mut value = ((n << 1) ^ (n >> 31)) as u32;
+        let mut out = Vec::new();
+        loop {
+            let b = (value & 0x7F) as u8;
+            value >>= 7;
+            if value == 0 {
+                out.push(b);
+                return out;
+            }
+            out.push(b | 0x80);
+        }
+    }
+
+    fn gear(item: DecodedItem) -> GearItem {
+        match item {
+            DecodedItem::Gear(g) => g,
+            DecodedItem::Crafted(_) => panic!("expected a gear item"),
+        }
+    }
+
+    fn crafted(item: DecodedItem) -> CraftedItem {
+        match item {
+            DecodedItem::Crafted(c) => c,
+            DecodedItem::Gear(_) => panic!("expected a crafted item"),
+        }
+    }
+
+    #[test]
+    fn legacy_gear() {
+        // 11 is 1 and 5 in base 6, so an earth powder followed by an air powder
+        let input = legacy_string("Warp", &[12, 40, 7], &[11], 3);
+        let item = gear(decode(&input).unwrap());
+
+        assert_eq!(item.name, "Warp");
+        assert!(matches!(&item.ids, GearIds::Legacy(ids) if ids == &[12, 40, 7]));
+        assert!(matches!(
+            item.powders.as_slice(),
+            [Powders::EARTH, Powders::AIR]
+        ));
+        assert_eq!(item.rerolls, 3);
+    }
+
+    #[test]
+    fn legacy_perfect_item() {
+        // the format `maxid` replies with, every id at 100% and no powders
+        let input = "󵿰Warp󵿲󵀸󵁸󵀀󵿱";
+        let item = gear(decode(input).unwrap());
+
+        assert_eq!(item.name, "Warp");
+        assert!(matches!(&item.ids, GearIds::Legacy(ids) if ids.starts_with(&[56, 120])));
+        assert!(item.powders.is_empty());
+        assert_eq!(item.rerolls, 0);
+    }
+
+    #[test]
+    fn legacy_without_start() {
+        let input = legacy_string("Warp", &[12, 40], &[], 3);
+        let item = gear(decode(input.trim_start_matches(START_CHAR)).unwrap());
+
+        assert_eq!(item.name, "Warp");
+        assert_eq!(item.rerolls, 3);
+    }
+
+    #[test]
+    fn legacy_round_trip() {
+        let ids = [0, 1, 2, 130, 2000];
+        let input = legacy_string("Cataclysm", &ids, &[], 0);
+        let item = gear(decode(&input).unwrap());
+
+        assert_eq!(item.name, "Cataclysm");
+        assert!(matches!(&item.ids, GearIds::Legacy(decoded) if decoded == &ids));
+        assert!(item.powders.is_empty());
+        assert_eq!(item.rerolls, 0);
+    }
+
+    #[test]
+    fn v1_gear() {
+        let mut bytes = vec![0, 0, 1, 0, 2];
+        bytes.extend_from_slice(b"Ignis\0");
+        // walk speed rolled at 130% and strength at 101%
+        bytes.extend_from_slice(&[3, 2, 0, 31, 130, 0, 101]);
+        // three slots holding an earth and an air powder
+        bytes.extend_from_slice(&[4, 3, 2, 0, 6, 4, 6]);
+        // the 255 rerolls and the end block share a character from the second private use area
+        bytes.extend_from_slice(&[5, 0xFF, 255]);
+
+        let item = gear(decode(&v1_string(&bytes)).unwrap());
+
+        assert_eq!(item.name, "Ignis");
+        match &item.ids {
+            GearIds::Rolls(rolls) => {
+                assert_eq!(rolls.len(), 2);
+                assert_eq!(rolls[&Identification::walkSpeed], 130);
+                assert_eq!(rolls[&Identification::rawStrength], 101);
+            }
+            GearIds::Legacy(_) => panic!("expected rolls"),
+        }
+        assert!(matches!(
+            item.powders.as_slice(),
+            [Powders::EARTH, Powders::AIR]
+        ));
+        assert_eq!(item.rerolls, 255);
+    }
+
+    #[test]
+    fn v1_crafted() {
+        let mut bytes = vec![0, 0, 1, 3, 2];
+        bytes.extend_from_slice(b"Crafted Spear\0");
+        // spear
+        bytes.extend_from_slice(&[7, 0]);
+        // level 100, no class and 50 strength
+        bytes.extend_from_slice(&[9, 100, 0, 1, 0]);
+        bytes.extend(varint(50));
+        // normal attack speed and 10-20 earth damage
+        bytes.extend_from_slice(&[10, 3, 1, 2]);
+        bytes.extend(varint(10));
+        bytes.extend(varint(20));
+        // health of -300 and no defences
+        bytes.push(11);
+        bytes.extend(varint(-300));
+        bytes.push(0);
+        // raw health from -5 to 30
+        bytes.extend_from_slice(&[12, 1, 11]);
+        bytes.extend(varint(-5));
+        bytes.extend(varint(30));
+        // 140 of 150 durability
+        bytes.extend_from_slice(&[8, 100]);
+        bytes.extend(varint(150));
+        bytes.extend(varint(140));
+        bytes.push(255);
+
+        let item = crafted(decode(&v1_string(&bytes)).unwrap());
+
+        assert_eq!(item.name, "Crafted Spear");
+        assert!(matches!(item.kind, CraftedKind::Gear(Type::SPEAR)));
+        assert!(matches!(item.attack_speed, Some(AttackSpeed::NORMAL)));
+        assert_eq!(item.level, 100);
+        assert_eq!(item.skills, [50, 0, 0, 0, 0]);
+        assert_eq!(item.damages, [None, Some((10, 20)), None, None, None, None]);
+        assert_eq!(item.health, Some(-300));
+        assert_eq!(item.defences, [None; 5]);
+        assert_eq!(item.ids.len(), 1);
+        assert_eq!(item.ids[&Identification::rawHealth], (-5, 30));
+        assert_eq!(item.durability, Some((140, 150)));
+    }
+
+    #[test]
+    fn v1_identification_ids() {
+        let expected = [
+            (0, Identification::rawStrength),
+            (5, Identification::attackSpeed),
+            (11, Identification::rawHealth),
+            (31, Identification::walkSpeed),
+            (42, Identification::raw1stSpellCost),
+            (49, Identification::SpellCost4),
+        ];
+
+        for (id, identification) in expected {
+            let bytes = [0, 0, 1, 0, 2, b'x', 0, 3, 1, 0, id, 100, 255];
+            let item = gear(decode(&v1_string(&bytes)).unwrap());
+            match &item.ids {
+                GearIds::Rolls(rolls) => assert_eq!(rolls.get(&identification), Some(&100)),
+                GearIds::Legacy(_) => panic!("expected rolls"),
+            }
+        }
+
+        // ids past the end of the table are rejected instead of being misread
+        let bytes = [0, 0, 1, 0, 2, b'x', 0, 3, 1, 0, 50, 100, 255];
+        assert!(decode(&v1_string(&bytes)).is_err());
+    }
+
+    #[test]
+    fn unknown_format() {
+        assert!(matches!(decode("Warp"), Err(DecodeError::UnknownFormat)));
+    }
+}
@@ -0,0 +1,108 @@
+use crate::{gen_embed_footer, Context, Error};
+
+/// Post territory takeovers to this channel
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR",
+    subcommands("subscribe", "unsubscribe", "status")
+)]
+pub async fn feed(ctx: Context<'_>) -> Result<(), Error> {
+    status_msg(ctx).await
+}
+
+/// Subscribe this channel to territory takeovers
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR"
+)]
+pub async fn subscribe(
+    ctx: Context<'_>,
+    #[description = "Only post takeovers involving these guilds, separated by commas"]
+    guilds: Option<String>,
+) -> Result<(), Error> {
+    let guilds: Vec<String> = guilds
+        .unwrap_or_default()
+        .split(',')
+        .map(|g| g.trim().to_string())
+        .filter(|g| !g.is_empty())
+        .collect();
+
+    ctx.data()
+        .feed
+        .subscribe(ctx.channel_id().0, guilds)
+        .await?;
+
+    status_msg(ctx).await
+}
+
+/// Stop posting territory takeovers to this channel
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR"
+)]
+pub async fn unsubscribe(ctx: Context<'_>) -> Result<(), Error> {
+    let removed = ctx.data().feed.unsubscribe(ctx.channel_id().0).await?;
+
+    let desc = if removed {
+        "This channel will no longer receive territory takeovers"
+    } else {
+        "This channel was not subscribed to territory takeovers"
+    };
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title("Territory feed");
+            e.description(desc);
+            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Show the territory feed subscription of this channel
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR"
+)]
+pub async fn status(ctx: Context<'_>) -> Result<(), Error> {
+    status_msg(ctx).await
+}
+
+/// Reply with the current subscription of the channel
+async fn status_msg(ctx: Context<'_>) -> Result<(), Error> {
+    let desc = match ctx.data().feed.subscription(ctx.channel_id().0).await {
+        Some(sub) if sub.guilds.is_empty() => {
+            String::from("This channel receives takeovers for every guild")
+        }
+        Some(sub) => format!(
+            "This channel receives takeovers involving: {}",
+            sub.guilds.join(", ")
+        ),
+        None => String::from("This channel is not subscribed to territory takeovers"),
+    };
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title("Territory feed");
+            e.description(desc);
+            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
@@ -1,39 +1,63 @@
 use std::{borrow::Cow, collections::HashMap};
 
-use image::{DynamicImage, ImageBuffer, Rgba};
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgba};
 use imageproc::{drawing, rect::Rect};
-use once_cell::sync::OnceCell;
+use rusttype::{Font, Scale};
 
-use image::io::Reader as ImageReader;
 use poise::serenity_prelude::AttachmentType;
 
-use crate::gen_embed_footer;
+use crate::basemap::{load_image, Projection};
+use crate::commands::map::{
+    autocomplete_basemap, crop_bounds, crop_region, find_territory, get_font, get_map, text_bounds,
+    unknown_basemap, Region, REGION_PADDING,
+};
+use crate::commands::whereis::draw_marker;
+use crate::encoder::{encode, pick_format, upload_limit, ImageFormat};
 use crate::error::create_error_msg;
+use crate::gen_embed_footer_note;
+use crate::render::render;
 use crate::{
-    wynn::Gather::{self, GatherSpot, GatherSpots},
+    wynn::world::TerritoryLocation,
+    wynn::Gather::{self, normalize_material, GatherSpot, GatherSpots, Profession},
     Context, Error,
 };
 
-/// Static for the gray image file so we don't have to load it every time
-static MAPBASE_GRAY: OnceCell<image::ImageBuffer<Rgba<u8>, Vec<u8>>> = OnceCell::new();
+/// Highest material tier
+const MAX_TIER: u8 = 12;
 
-/// Helper function for getting the base map
-fn get_mapbase_gray() -> Result<image::ImageBuffer<Rgba<u8>, Vec<u8>>, Error> {
-    let out = if let Some(pm) = MAPBASE_GRAY.get() {
-        pm.clone()
-    } else {
-        let map: image::ImageBuffer<Rgba<u8>, Vec<u8>> = if let DynamicImage::ImageRgba8(img) =
-            ImageReader::open("./resources/main-map-gray.png")?.decode()?
-        {
-            img
-        } else {
-            panic!("main-map-gray.png invalid!!!");
-        };
-        MAPBASE_GRAY.set(map.clone()).unwrap();
-        map
-    };
-    Ok(out)
-}
+/// Number of spots shown around a location by default
+const NEAR_SPOTS: usize = 5;
+
+/// Most spots that can be shown around a location
+const MAX_NEAR_SPOTS: usize = 25;
+
+/// Smallest area shown around the matches in blocks
+const MIN_CROP_SIZE: f64 = 600.0;
+
+/// Width of the overview map compared to the width of the cropped map
+const OVERVIEW_FRACTION: f64 = 0.2;
+
+/// Space between the overview map and the edges of the image in pixels
+const OVERVIEW_MARGIN: u32 = 8;
+
+/// Most clusters listed in the embed
+const MAX_LISTED_CLUSTERS: usize = 10;
+
+/// Font size of the spot count on clusters
+const CLUSTER_LABEL_SIZE: f32 = 16.0;
+
+/// Compass directions starting at north going clockwise
+const DIRECTIONS: [&str; 8] = [
+    "north",
+    "north east",
+    "east",
+    "south east",
+    "south",
+    "south west",
+    "west",
+    "north west",
+];
 
 /// Finds gather spots and renders them to a map
 #[poise::command(prefix_command, slash_command)]
@@ -41,113 +65,248 @@ pub async fn gather(
     ctx: Context<'_>,
     #[rest]
     #[description = "Name of the material you want to query"]
-    material: String,
+    #[autocomplete = "autocomplete_material"]
+    material: Option<String>,
+    #[description = "Only show spots of this profession"] profession: Option<Profession>,
+    #[description = "Only show materials of this tier, 1 being the lowest level material"]
+    tier: Option<u8>,
+    #[description = "Only show the closest spots to a territory or coordinates as `x, z`"]
+    near: Option<String>,
+    #[description = "Number of spots to show with `near` (default: 5)"] count: Option<usize>,
+    #[description = "Base map to draw on"]
+    #[autocomplete = "autocomplete_basemap"]
+    basemap: Option<String>,
+    #[description = "Format of the image"] format: Option<ImageFormat>,
 ) -> Result<(), Error> {
-    let wanted = material.to_ascii_uppercase();
+    let base = if let Some(b) = ctx.data().config.base_map(basemap.as_deref()) {
+        b
+    } else {
+        unknown_basemap(ctx).await;
+        return Ok(());
+    };
+
+    if material.is_none() && profession.is_none() && tier.is_none() {
+        create_error_msg(
+            ctx,
+            "Nothing to search for",
+            "Give a material, profession or tier to search for",
+        )
+        .await;
+        return Ok(());
+    }
+
+    if let Some(t) = tier {
+        if !(1..=MAX_TIER).contains(&t) {
+            create_error_msg(
+                ctx,
+                "Invalid tier",
+                &format!("Tiers go from 1 to {}", MAX_TIER),
+            )
+            .await;
+            return Ok(());
+        }
+    }
+
+    let filter = SpotFilter {
+        material: material.as_deref().map(normalize_material),
+        profession,
+        tier,
+    };
 
     // defer here so we can respond with an image and discord knows that it might take a while before we respond
     ctx.defer().await?;
 
     let spots = Gather::get_gatherspots().await?;
 
-    let mut count = 0;
-    let mut spots_wood = Vec::new();
-    let mut spots_mining = Vec::new();
-    let mut spots_farming = Vec::new();
-    let mut spots_fishing = Vec::new();
-
-    for spot in &spots.woodCutting {
-        if spot.r#type.contains(&wanted) || wanted.contains(&spot.r#type) {
-            count += 1;
-            spots_wood.push(spot.clone());
-            // add_rect(spot, Rgba([0, 255, 0, 255]), &mut out);
-        }
-    }
-    for spot in &spots.mining {
-        if spot.r#type.contains(&wanted) || wanted.contains(&spot.r#type) {
-            count += 1;
-            spots_mining.push(spot.clone());
-            // add_rect(spot, Rgba([255, 0, 0, 255]), &mut out);
-        }
-    }
-    for spot in &spots.farming {
-        if spot.r#type.contains(&wanted) || wanted.contains(&spot.r#type) {
-            count += 1;
-            spots_farming.push(spot.clone());
-            // add_rect(spot, Rgba([255, 255, 0, 255]), &mut out);
-        }
-    }
-    for spot in &spots.fishing {
-        if spot.r#type.contains(&wanted) || wanted.contains(&spot.r#type) {
-            count += 1;
-            spots_fishing.push(spot.clone());
-            // add_rect(spot, Rgba([0, 0, 255, 255]), &mut out);
-        }
-    }
-
-    if count == 0 {
-        let mut alltypes = String::new();
-        let types = get_all_res(&spots);
+    let mut matched: Vec<(Profession, GatherSpot)> = spots
+        .all()
+        .filter(|(prof, spot)| filter.matches(*prof, spot))
+        .map(|(prof, spot)| (prof, spot.clone()))
+        .collect();
+    let total = matched.len();
 
-        for t in types {
-            alltypes.push_str(&format!("`{}`, ", t.0.to_lowercase()));
-        }
+    if total == 0 {
+        let mut types: Vec<String> = get_all_res(&spots)
+            .into_keys()
+            .map(|t| format!("`{}`", t.to_lowercase()))
+            .collect();
+        types.sort_unstable();
 
         create_error_msg(
             ctx,
             "No matches",
             &format!(
-                "The current filter `{}` did not match any known resources\nCurrent known resource types are:\n{}",
-                wanted,
-                alltypes
+                "No known resources match {}\nCurrent known resource types are:\n{}",
+                filter.describe(),
+                types.join(", ")
             ),
         )
         .await;
         return Ok(());
     }
 
-    let mut out = drawing::Blend(get_mapbase_gray()?);
+    let origin = match near {
+        Some(near) => {
+            if let Some(o) = find_origin(&near).await? {
+                Some(o)
+            } else {
+                create_error_msg(
+                    ctx,
+                    "Unknown location",
+                    &format!(
+                        "`{}` is neither a territory nor coordinates given as `x, z`",
+                        near
+                    ),
+                )
+                .await;
+                return Ok(());
+            }
+        }
+        None => None,
+    };
 
-    // render the spots
-    for spot in &spots_wood {
-        add_rect(spot, Rgba([0, 255, 0, 255]), &mut out);
-    }
-    for spot in &spots_mining {
-        add_rect(spot, Rgba([255, 0, 0, 255]), &mut out);
-    }
-    for spot in &spots_farming {
-        add_rect(spot, Rgba([255, 255, 0, 255]), &mut out);
-    }
-    for spot in &spots_fishing {
-        add_rect(spot, Rgba([0, 0, 255, 255]), &mut out);
-    }
+    let mut title = format!("{} matches", total);
+    let mut desc = format!("Spots matching {}", filter.describe());
 
-    // encode image as webp
-    let img_data: Vec<u8>;
+    // only keep the closest spots and zoom in on them
+    let mut crop = None;
+    if let Some(origin) = &origin {
+        let from = (origin.x, origin.z);
+        matched.sort_by(|a, b| {
+            distance(from, spot_pos(&a.1))
+                .partial_cmp(&distance(from, spot_pos(&b.1)))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+        matched.truncate(count.unwrap_or(NEAR_SPOTS).clamp(1, MAX_NEAR_SPOTS));
 
-    {
-        let img = &DynamicImage::ImageRgba8(out.0);
+        title = format!("{} closest of {} matches", matched.len(), total);
+        desc = format!(
+            "Closest spots matching {} to {}\n",
+            filter.describe(),
+            origin.name
+        );
+        let mut region = Region::point(origin.x, origin.z);
+        if let Some(loc) = &origin.territory {
+            region.include(loc);
+        }
 
-        let encoder = webp::Encoder::from_image(img)?;
-        let encoded = encoder.encode(ctx.data().config.image.webp_quality);
+        for (prof, spot) in &matched {
+            let to = spot_pos(spot);
+            desc.push_str(&format!(
+                "{} {} at `{:.0}, {:.0}`, `{:.0}` blocks {}\n",
+                spot.r#type.to_lowercase(),
+                prof.name().to_lowercase(),
+                to.0,
+                to.1,
+                distance(from, to),
+                direction(from, to)
+            ));
+            region.include_point(to.0, to.1);
+        }
 
-        img_data = (*encoded).to_vec();
+        crop = Some(region.pad(REGION_PADDING));
+    } else if let Some((_, first)) = matched.first() {
+        // zoom in on the area with matches
+        let mut region = Region::point(first.location.x, first.location.z);
+        for (_, spot) in &matched {
+            region.include_point(spot.location.x, spot.location.z);
+        }
+        crop = Some(region.pad(REGION_PADDING).at_least(MIN_CROP_SIZE));
     }
 
+    let cluster_radius = ctx.data().config.gather.cluster_radius;
+    let cluster_min_spots = ctx.data().config.gather.cluster_min_spots;
+
+    let base = base.clone();
+    let quality = ctx.data().config.image.webp_quality;
+    let format = pick_format(ctx, format).await;
+    let limit = upload_limit(ctx);
+
+    // clustering compares every spot with every other one so it runs on the render threads too
+    let (encoded, cluster_list) = render(ctx, move || {
+        let clusters = cluster_spots(&matched, cluster_radius, cluster_min_spots);
+        // the nearby spots are already listed one by one
+        let cluster_list = match &origin {
+            Some(_) => String::new(),
+            None => list_clusters(&clusters),
+        };
+
+        let mut out = drawing::Blend(load_image(base.gather_image())?.as_ref().clone());
+        let proj = &base.projection;
+
+        // render the spots, dense areas are drawn as a single circle
+        let font = get_font();
+        // everything drawn as a circle in pixels so the overview can stay clear of it
+        let mut marks = Vec::new();
+        for cluster in &clusters {
+            if cluster.spots.len() > 1 {
+                draw_cluster(cluster, proj, &font, &mut out);
+                marks.push((
+                    proj.to_pixel(cluster.center),
+                    cluster_pixel_radius(cluster, proj) as f64,
+                ));
+            } else {
+                for spot in &cluster.spots {
+                    add_rect(spot, proj, profession_color(cluster.profession), &mut out);
+                    marks.push((proj.to_pixel(spot_pos(spot)), 3.0));
+                }
+            }
+        }
+
+        // mark where the distances are measured from
+        if let Some(origin) = &origin {
+            if let Some(loc) = &origin.territory {
+                let (x1, y1) = proj.to_pixel((loc.min_x(), loc.min_z()));
+                let (x2, y2) = proj.to_pixel((loc.max_x(), loc.max_z()));
+                drawing::draw_hollow_rect_mut(
+                    &mut out,
+                    Rect::at(x1 as i32, y1 as i32)
+                        .of_size(((x2 - x1) as u32).max(1), ((y2 - y1) as u32).max(1)),
+                    Rgba([255, 255, 255, 255]),
+                );
+            }
+            draw_marker(&mut out, proj, (origin.x, origin.z));
+            marks.push((proj.to_pixel((origin.x, origin.z)), 5.0));
+        }
+
+        let img = match crop {
+            Some(region) => {
+                let bounds = crop_bounds(out.0.dimensions(), proj, region);
+                if bounds.2 >= out.0.width() && bounds.3 >= out.0.height() {
+                    // the matches are all over the map so there is nothing to zoom in on
+                    out.0
+                } else {
+                    let mut img = crop_region(&out.0, proj, region, None);
+                    draw_overview(&mut img, &out.0, bounds, &marks);
+                    img
+                }
+            }
+            None => out.0,
+        };
+
+        Ok((encode(img, format, quality, limit)?, cluster_list))
+    })
+    .await?;
+    desc.push_str(&cluster_list);
+
+    let filename = encoded.filename("map");
+    let note = encoded.describe();
+
     // serenity wants a cow for whatever reason
-    let cow = Cow::from(img_data);
+    let cow = Cow::from(encoded.data);
 
     // construct reply message
     ctx.send(|m| {
         m.embed(|e| {
-            e.title(format!("{} matches", count));
-            e.image("attachment://map.webp");
-            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e.title(title);
+            e.description(desc);
+            e.image(format!("attachment://{}", filename));
+            gen_embed_footer_note(e, &ctx.data().config.bot.name, &note);
             e
         });
         m.attachment(AttachmentType::Bytes {
             data: cow,
-            filename: String::from("map.webp"),
+            filename: filename.clone(),
         });
         m
     })
@@ -156,24 +315,392 @@ pub async fn gather(
     Ok(())
 }
 
-/// calculate the x offset
-fn calc_x(x: f64) -> f64 {
-    (x / 3.0) - 566.333 + 1364.0
+/// Point that distances to spots are measured from
+struct Origin {
+    x: f64,
+    z: f64,
+    /// Name of the origin for messages
+    name: String,
+    /// Area of the territory if the origin is one
+    territory: Option<TerritoryLocation>,
+}
+
+/// Find the point given with the `near` option
+///
+/// Coordinates are tried first, anything else is looked up as a territory name.
+async fn find_origin(near: &str) -> Result<Option<Origin>, Error> {
+    let nums: Vec<f64> = near
+        .split(|c: char| c.is_whitespace() || c == ',')
+        .filter(|p| !p.is_empty())
+        .map(|p| p.parse())
+        .collect::<Result<_, _>>()
+        .unwrap_or_default();
+    if let [x, z] = nums[..] {
+        return Ok(Some(Origin {
+            x,
+            z,
+            name: format!("`{:.0}, {:.0}`", x, z),
+            territory: None,
+        }));
+    }
+
+    let terrs = get_map().await?;
+    Ok(find_territory(&terrs, near).map(|t| {
+        let (x, z) = t.location.center();
+        Origin {
+            x,
+            z,
+            name: t.territory.clone(),
+            territory: Some(t.location),
+        }
+    }))
 }
 
-/// calculate the y offset
-fn calc_z(z: f64) -> f64 {
-    (z / 3.0) + 41.0 + 2162.0
+/// Position of a spot as `(x, z)`
+fn spot_pos(spot: &GatherSpot) -> (f64, f64) {
+    (spot.location.x, spot.location.z)
+}
+
+/// Distance between two points
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
+}
+
+/// Compass direction from one point to another, north being towards negative z
+fn direction(from: (f64, f64), to: (f64, f64)) -> &'static str {
+    let angle = (to.0 - from.0).atan2(from.1 - to.1).to_degrees();
+    DIRECTIONS[((angle / 45.0).round() as i64).rem_euclid(8) as usize]
+}
+
+/// Spots of the same material close to each other
+struct Cluster {
+    profession: Profession,
+    material: String,
+    /// Average position of the spots
+    center: (f64, f64),
+    /// Distance from the centre to the furthest spot
+    radius: f64,
+    spots: Vec<GatherSpot>,
+}
+
+impl Cluster {
+    fn new(profession: Profession, material: &str, spots: Vec<GatherSpot>) -> Self {
+        let n = spots.len().max(1) as f64;
+        let center = (
+            spots.iter().map(|s| s.location.x).sum::<f64>() / n,
+            spots.iter().map(|s| s.location.z).sum::<f64>() / n,
+        );
+        let radius = spots
+            .iter()
+            .map(|s| distance(center, spot_pos(s)))
+            .fold(0.0, f64::max);
+
+        Self {
+            profession,
+            material: material.to_string(),
+            center,
+            radius,
+            spots,
+        }
+    }
+}
+
+/// Group spots of the same material that are close to each other
+///
+/// Works like DBSCAN: a spot with at least `min_spots` spots within `radius` of it, counting
+/// itself, is a core spot. Clusters grow from core spots to every spot in range of one. Spots that
+/// don't end up in a cluster are returned as clusters of their own. The biggest clusters come first.
+fn cluster_spots(
+    spots: &[(Profession, GatherSpot)],
+    radius: f64,
+    min_spots: usize,
+) -> Vec<Cluster> {
+    let mut groups: HashMap<(Profession, &str), Vec<&GatherSpot>> = HashMap::new();
+    for (prof, spot) in spots {
+        groups.entry((*prof, &spot.r#type)).or_default().push(spot);
+    }
+
+    let mut out = Vec::new();
+    for ((prof, material), group) in groups {
+        let neighbours: Vec<Vec<usize>> = group
+            .iter()
+            .map(|a| {
+                (0..group.len())
+                    .filter(|j| distance(spot_pos(a), spot_pos(group[*j])) <= radius)
+                    .collect()
+            })
+            .collect();
+
+        let mut assigned = vec![false; group.len()];
+        for start in 0..group.len() {
+            if assigned[start] || neighbours[start].len() < min_spots {
+                continue;
+            }
+
+            let mut members = Vec::new();
+            let mut queue = vec![start];
+            assigned[start] = true;
+            while let Some(i) = queue.pop() {
+                members.push(group[i].clone());
+                // spots on the edge of a cluster don't pull in their own neighbours
+                if neighbours[i].len() < min_spots {
+                    continue;
+                }
+                for &j in &neighbours[i] {
+                    if !assigned[j] {
+                        assigned[j] = true;
+                        queue.push(j);
+                    }
+                }
+            }
+            out.push(Cluster::new(prof, material, members));
+        }
+
+        for (i, spot) in group.iter().enumerate() {
+            if !assigned[i] {
+                out.push(Cluster::new(prof, material, vec![(*spot).clone()]));
+            }
+        }
+    }
+
+    out.sort_by(|a, b| {
+        b.spots
+            .len()
+            .cmp(&a.spots.len())
+            .then_with(|| a.material.cmp(&b.material))
+            .then_with(|| {
+                a.center
+                    .partial_cmp(&b.center)
+                    .unwrap_or(std::cmp::Ordering::Equal)
+            })
+    });
+    out
+}
+
+/// List the clusters with more than one spot for the embed, the biggest ones first
+fn list_clusters(clusters: &[Cluster]) -> String {
+    let dense: Vec<&Cluster> = clusters.iter().filter(|c| c.spots.len() > 1).collect();
+    if dense.is_empty() {
+        return String::new();
+    }
+
+    let mut out = String::from("\n\n**Clusters**\n");
+    for cluster in dense.iter().take(MAX_LISTED_CLUSTERS) {
+        out.push_str(&format!(
+            "{} {} spots around `{:.0}, {:.0}`\n",
+            cluster.spots.len(),
+            cluster.material.to_lowercase(),
+            cluster.center.0,
+            cluster.center.1
+        ));
+    }
+    if dense.len() > MAX_LISTED_CLUSTERS {
+        out.push_str(&format!("and {} more\n", dense.len() - MAX_LISTED_CLUSTERS));
+    }
+    out
+}
+
+/// Draws a cluster as a circle covering its spots with the number of spots in the middle
+fn draw_cluster(
+    cluster: &Cluster,
+    proj: &Projection,
+    font: &Font,
+    img: &mut drawing::Blend<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+) {
+    let (px, py) = proj.to_pixel(cluster.center);
+    let center = (px as i32, py as i32);
+    let radius = cluster_pixel_radius(cluster, proj);
+
+    let col = profession_color(cluster.profession);
+    drawing::draw_filled_circle_mut(
+        img,
+        center,
+        radius,
+        Rgba([col.0[0], col.0[1], col.0[2], 110]),
+    );
+    drawing::draw_hollow_circle_mut(img, center, radius, col);
+
+    let label = cluster.spots.len().to_string();
+    let scale = Scale::uniform(CLUSTER_LABEL_SIZE);
+    if let Some(bounds) = text_bounds(font, scale, &label) {
+        let x = center.0 - bounds.width() / 2 - bounds.min.x;
+        let y = center.1 - bounds.height() / 2 - bounds.min.y;
+        for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            drawing::draw_text_mut(
+                img,
+                Rgba([0, 0, 0, 200]),
+                x + dx,
+                y + dy,
+                scale,
+                font,
+                &label,
+            );
+        }
+        drawing::draw_text_mut(img, Rgba([255, 255, 255, 255]), x, y, scale, font, &label);
+    }
+}
+
+/// Radius of the circle a cluster is drawn as in pixels
+fn cluster_pixel_radius(cluster: &Cluster, proj: &Projection) -> i32 {
+    (proj.length(cluster.radius) as i32 + 4).max(8)
+}
+
+/// Draws a small copy of the whole map in a corner with the cropped area marked
+///
+/// `bounds` is the cropped area in pixels of the whole map as `(x, y, width, height)`. The
+/// overview goes in the first corner that doesn't cover any of `marks`, circles given as centre
+/// and radius in pixels of the whole map, starting at the bottom right. If every corner covers
+/// something the one covering the fewest marks is used.
+fn draw_overview(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    full: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    (crop_x, crop_y, crop_width, crop_height): (u32, u32, u32, u32),
+    marks: &[((f64, f64), f64)],
+) {
+    // keep the overview from covering too much of tall or wide crops
+    let scale = (img.width() as f64 * OVERVIEW_FRACTION / full.width() as f64)
+        .min(img.height() as f64 / 3.0 / full.height() as f64);
+    let width = (full.width() as f64 * scale) as u32;
+    let height = (full.height() as f64 * scale) as u32;
+
+    if width < 16 || height < 16 {
+        return;
+    }
+
+    // the cropped area was scaled up to the size of the image
+    let zoom_x = img.width() as f64 / crop_width as f64;
+    let zoom_y = img.height() as f64 / crop_height as f64;
+    let covered = |(x, y): (u32, u32)| {
+        // include the border around the overview
+        let (left, top) = (x as f64 - 1.0, y as f64 - 1.0);
+        let (right, bottom) = (left + width as f64 + 2.0, top + height as f64 + 2.0);
+        marks
+            .iter()
+            .filter(|((px, py), radius)| {
+                let cx = (px - crop_x as f64) * zoom_x;
+                let cy = (py - crop_y as f64) * zoom_y;
+                let dx = cx - cx.clamp(left, right);
+                let dy = cy - cy.clamp(top, bottom);
+                (dx * dx + dy * dy).sqrt() <= radius * zoom_x.max(zoom_y)
+            })
+            .count()
+    };
+
+    let right = img.width().saturating_sub(width + OVERVIEW_MARGIN);
+    let bottom = img.height().saturating_sub(height + OVERVIEW_MARGIN);
+    let (x, y) = [
+        (right, bottom),
+        (OVERVIEW_MARGIN, bottom),
+        (right, OVERVIEW_MARGIN),
+        (OVERVIEW_MARGIN, OVERVIEW_MARGIN),
+    ]
+    .into_iter()
+    .min_by_key(|corner| covered(*corner))
+    .unwrap_or((right, bottom));
+    let (x, y) = (x as i32, y as i32);
+
+    let small = imageops::resize(full, width, height, FilterType::Triangle);
+    imageops::overlay(img, &small, x as i64, y as i64);
+
+    drawing::draw_hollow_rect_mut(
+        img,
+        Rect::at(x - 1, y - 1).of_size(width + 2, height + 2),
+        Rgba([255, 255, 255, 255]),
+    );
+    drawing::draw_hollow_rect_mut(
+        img,
+        Rect::at(
+            x + (crop_x as f64 * scale) as i32,
+            y + (crop_y as f64 * scale) as i32,
+        )
+        .of_size(
+            ((crop_width as f64 * scale) as u32).max(2),
+            ((crop_height as f64 * scale) as u32).max(2),
+        ),
+        Rgba([255, 0, 0, 255]),
+    );
+}
+
+/// Which gather spots to show
+struct SpotFilter {
+    /// Normalized name of the material
+    material: Option<String>,
+    profession: Option<Profession>,
+    tier: Option<u8>,
+}
+
+impl SpotFilter {
+    /// Whether a spot passes all of the given filters
+    fn matches(&self, prof: Profession, spot: &GatherSpot) -> bool {
+        if let Some(material) = &self.material {
+            if normalize_material(&spot.r#type) != *material {
+                return false;
+            }
+        }
+        if let Some(p) = self.profession {
+            if p != prof {
+                return false;
+            }
+        }
+        if let Some(t) = self.tier {
+            if prof.tier(&spot.r#type) != Some(t) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Description of the filters for messages
+    fn describe(&self) -> String {
+        let mut parts = Vec::new();
+        if let Some(material) = &self.material {
+            parts.push(format!("material `{}`", material.to_lowercase()));
+        }
+        if let Some(p) = self.profession {
+            parts.push(format!("profession `{}`", p.name().to_lowercase()));
+        }
+        if let Some(t) = self.tier {
+            parts.push(format!("tier `{}`", t));
+        }
+        parts.join(", ")
+    }
+}
+
+/// Colour spots of a profession are drawn in
+fn profession_color(prof: Profession) -> Rgba<u8> {
+    match prof {
+        Profession::Woodcutting => Rgba([0, 255, 0, 255]),
+        Profession::Mining => Rgba([255, 0, 0, 255]),
+        Profession::Farming => Rgba([255, 255, 0, 255]),
+        Profession::Fishing => Rgba([0, 0, 255, 255]),
+    }
+}
+
+/// Autocomplete material names from the spots that currently exist
+async fn autocomplete_material(_ctx: Context<'_>, partial: String) -> impl Iterator<Item = String> {
+    let partial = partial.to_lowercase();
+    let mut names: Vec<String> = match Gather::get_gatherspots().await {
+        Ok(spots) => get_all_res(&spots)
+            .into_keys()
+            .map(|t| t.to_lowercase())
+            .filter(|t| t.contains(&partial))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort_unstable();
+    names.truncate(25);
+    names.into_iter()
 }
 
 /// Adds a spot to the image
 fn add_rect(
     spot: &GatherSpot,
+    proj: &Projection,
     color: Rgba<u8>,
     img: &mut drawing::Blend<ImageBuffer<Rgba<u8>, Vec<u8>>>,
 ) {
-    let x = calc_x(spot.location.x) as i32;
-    let y = calc_z(spot.location.z) as i32;
+    let x = proj.x(spot.location.x) as i32;
+    let y = proj.z(spot.location.z) as i32;
 
     let rect = Rect::at(x - 3, y - 3).of_size(5, 5);
 
@@ -181,30 +708,13 @@ fn add_rect(
     drawing::draw_filled_rect_mut(img, rect, color);
 }
 
-/// Gets all of the resource types
-fn get_all_res<'a>(spots: &GatherSpots) -> HashMap<String, i32> {
+/// Gets all of the resource types and how many spots there are of each
+fn get_all_res(spots: &GatherSpots) -> HashMap<String, i32> {
     let mut out: HashMap<String, i32> = HashMap::new();
 
-    for s in &spots.woodCutting {
-        append_spots(&mut out, &s);
-    }
-    for s in &spots.mining {
-        append_spots(&mut out, &s);
-    }
-    for s in &spots.farming {
-        append_spots(&mut out, &s);
-    }
-    for s in &spots.fishing {
-        append_spots(&mut out, &s);
+    for (_, s) in spots.all() {
+        *out.entry(s.r#type.clone()).or_default() += 1;
     }
 
     out
 }
-
-fn append_spots(map: &mut HashMap<String, i32>, spot: &GatherSpot) {
-    if let Some(v) = map.get_mut(&spot.r#type) {
-        *v += 1;
-    } else {
-        map.insert(spot.r#type.clone(), 0);
-    }
-}
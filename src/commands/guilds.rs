@@ -0,0 +1,353 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use image::{ImageBuffer, Rgba};
+use imageproc::drawing;
+use imageproc::rect::Rect;
+use poise::serenity::builder::{CreateComponents, CreateEmbed};
+use poise::serenity::model::interactions::message_component::{
+    ButtonStyle, MessageComponentInteraction,
+};
+use poise::serenity::model::interactions::InteractionResponseType;
+use poise::serenity_prelude::{self as serenity, AttachmentType, Message};
+use rusttype::Scale;
+use tracing::warn;
+
+use crate::commands::map::{get_font, get_map, text_size};
+use crate::encoder::{encode, pick_format, upload_limit};
+use crate::palette::{guild_colors, GuildColors};
+use crate::render::render;
+use crate::util::format_duration;
+use crate::wynn::world::Territories;
+use crate::{gen_embed_footer_note, Context, Data, Error};
+
+/// Start of the custom id of the page buttons
+pub const BUTTON_PREFIX: &str = "guilds:";
+
+/// Guilds listed on a page
+const PAGE_SIZE: usize = 10;
+
+/// Guilds shown in the bar chart, the rest are added up into one bar
+const CHART_BARS: usize = 10;
+
+/// Width of the bar chart in pixels
+const CHART_WIDTH: u32 = 600;
+/// Height of a bar in pixels
+const BAR_HEIGHT: u32 = 24;
+/// Space between the bars and around the text in pixels
+const BAR_GAP: u32 = 6;
+
+/// A guild holding territories
+struct GuildEntry {
+    name: String,
+    prefix: String,
+    territories: usize,
+    /// The territory the guild has held the longest and since when
+    longest_held: Option<(String, DateTime<Utc>)>,
+}
+
+/// Show the guilds holding the most territories
+#[poise::command(prefix_command, slash_command)]
+pub async fn guilds(ctx: Context<'_>) -> Result<(), Error> {
+    // defer here so we can respond with an image and discord knows that it might take a while before we respond
+    ctx.defer().await?;
+
+    let terrs = get_map().await?;
+    let entries = leaderboard(&terrs);
+    let total = terrs.territories.len();
+    let colors = guild_colors(&terrs)?;
+
+    let format = pick_format(ctx, None).await;
+    let limit = upload_limit(ctx);
+    let quality = ctx.data().config.image.webp_quality;
+
+    let bars = chart_bars(&entries, &colors);
+    let encoded = render(ctx, move || {
+        encode(draw_chart(&bars), format, quality, limit)
+    })
+    .await?;
+
+    let filename = encoded.filename("guilds");
+    let image = format!("attachment://{}", filename);
+
+    // serenity wants a cow for whatever reason
+    let cow = Cow::from(encoded.data.as_slice());
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            page_embed(e, &ctx.data().config.bot.name, &entries, total, 0, &image);
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: cow,
+            filename: filename.clone(),
+        });
+        m.components(|c| page_buttons(c, &entries, 0));
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Handle a press of one of the page buttons
+///
+/// The list is built from the current territories, the chart is left as it was.
+pub async fn guilds_interact_handler(
+    ctx: &serenity::Context,
+    data: &Data,
+    msg: &Message,
+    interact: &MessageComponentInteraction,
+) -> Result<(), Error> {
+    let page = match parse_page(&interact.data.custom_id) {
+        Some(p) => p,
+        None => {
+            warn!("Invalid guilds button id `{}`", interact.data.custom_id);
+            return Ok(());
+        }
+    };
+
+    // loading the territories can take longer than discord waits for a response so respond first
+    interact
+        .create_interaction_response(&ctx, |r| {
+            r.kind(InteractionResponseType::DeferredUpdateMessage);
+            r
+        })
+        .await?;
+
+    let terrs = get_map().await?;
+    let entries = leaderboard(&terrs);
+    let page = page.min(page_count(&entries) - 1);
+
+    let image = msg
+        .attachments
+        .first()
+        .map(|a| format!("attachment://{}", a.filename))
+        .unwrap_or_default();
+
+    let mut message = msg.clone();
+    message
+        .edit(&ctx.http, |m| {
+            m.embed(|e| {
+                page_embed(
+                    e,
+                    &data.config.bot.name,
+                    &entries,
+                    terrs.territories.len(),
+                    page,
+                    &image,
+                );
+                e
+            });
+            m.components(|c| page_buttons(c, &entries, page));
+            m
+        })
+        .await?;
+
+    Ok(())
+}
+
+/// Group the territories by guild, sorted from the most territories to the least
+fn leaderboard(terrs: &Territories) -> Vec<GuildEntry> {
+    let mut guilds: HashMap<&str, GuildEntry> = HashMap::new();
+
+    for terr in terrs.territories.values() {
+        let entry = guilds.entry(&terr.guild).or_insert_with(|| GuildEntry {
+            name: terr.guild.clone(),
+            prefix: terr.guildPrefix.clone(),
+            territories: 0,
+            longest_held: None,
+        });
+        entry.territories += 1;
+
+        if let Some(acquired) = terr.acquired_time() {
+            let older = match &entry.longest_held {
+                Some((_, time)) => acquired < *time,
+                None => true,
+            };
+            if older {
+                entry.longest_held = Some((terr.territory.clone(), acquired));
+            }
+        }
+    }
+
+    let mut entries: Vec<GuildEntry> = guilds.into_values().collect();
+    entries.sort_by(|a, b| {
+        b.territories
+            .cmp(&a.territories)
+            .then_with(|| a.name.cmp(&b.name))
+    });
+    entries
+}
+
+/// Number of pages needed for the list, always at least one
+fn page_count(entries: &[GuildEntry]) -> usize {
+    ((entries.len() + PAGE_SIZE - 1) / PAGE_SIZE).max(1)
+}
+
+/// Fill in the embed for a page of the list
+fn page_embed(
+    e: &mut CreateEmbed,
+    bot_name: &str,
+    entries: &[GuildEntry],
+    total: usize,
+    page: usize,
+    image: &str,
+) {
+    let now = Utc::now();
+    let mut desc = String::new();
+
+    for (i, entry) in entries
+        .iter()
+        .enumerate()
+        .skip(page * PAGE_SIZE)
+        .take(PAGE_SIZE)
+    {
+        desc.push_str(&format!(
+            "**{}.** {} [{}] - {} territories ({:.1}%)\n",
+            i + 1,
+            entry.name,
+            entry.prefix,
+            entry.territories,
+            entry.territories as f64 / total.max(1) as f64 * 100.0
+        ));
+        if let Some((terr, since)) = &entry.longest_held {
+            desc.push_str(&format!(
+                "Longest held: {} for `{}`\n",
+                terr,
+                format_duration((now - *since).num_seconds())
+            ));
+        }
+    }
+
+    e.title(format!(
+        "{} guilds holding {} territories",
+        entries.len(),
+        total
+    ));
+    e.description(desc);
+    if !image.is_empty() {
+        e.image(image);
+    }
+    gen_embed_footer_note(
+        e,
+        bot_name,
+        &format!("page {}/{}", page + 1, page_count(entries)),
+    );
+}
+
+/// Add the previous and next page buttons
+fn page_buttons<'a>(
+    c: &'a mut CreateComponents,
+    entries: &[GuildEntry],
+    page: usize,
+) -> &'a mut CreateComponents {
+    let last = page_count(entries) - 1;
+
+    c.create_action_row(|ar| {
+        ar.create_button(|b| {
+            b.style(ButtonStyle::Primary);
+            b.label("Previous");
+            b.custom_id(format!("{}prev:{}", BUTTON_PREFIX, page.saturating_sub(1)));
+            b.disabled(page == 0);
+            b
+        });
+        ar.create_button(|b| {
+            b.style(ButtonStyle::Primary);
+            b.label("Next");
+            b.custom_id(format!("{}next:{}", BUTTON_PREFIX, (page + 1).min(last)));
+            b.disabled(page >= last);
+            b
+        });
+        ar
+    });
+    c
+}
+
+/// Read the page a button leads to from its custom id
+fn parse_page(id: &str) -> Option<usize> {
+    let (_, page) = id.strip_prefix(BUTTON_PREFIX)?.split_once(':')?;
+    page.parse().ok()
+}
+
+/// Label, territory count and colour of every bar in the chart
+fn chart_bars(entries: &[GuildEntry], colors: &GuildColors) -> Vec<(String, usize, (u8, u8, u8))> {
+    let mut bars: Vec<(String, usize, (u8, u8, u8))> = entries
+        .iter()
+        .take(CHART_BARS)
+        .map(|e| {
+            let label = if e.prefix.is_empty() {
+                e.name.clone()
+            } else {
+                e.prefix.clone()
+            };
+            let col = colors.get(&e.name).copied().unwrap_or((128, 128, 128));
+            (label, e.territories, col)
+        })
+        .collect();
+
+    if entries.len() > CHART_BARS {
+        let rest = entries[CHART_BARS..].iter().map(|e| e.territories).sum();
+        bars.push((String::from("Others"), rest, (128, 128, 128)));
+    }
+
+    bars
+}
+
+/// Draw a horizontal bar chart with the label before and the count after every bar
+fn draw_chart(bars: &[(String, usize, (u8, u8, u8))]) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let font = get_font();
+    let scale = Scale::uniform(BAR_HEIGHT as f32 * 0.75);
+
+    let height = BAR_GAP + (BAR_HEIGHT + BAR_GAP) * bars.len().max(1) as u32;
+    let mut img = ImageBuffer::from_pixel(CHART_WIDTH, height, Rgba([47, 49, 54, 255]));
+
+    let label_width = bars
+        .iter()
+        .map(|(label, _, _)| text_size(&font, scale, label).0)
+        .max()
+        .unwrap_or(0)
+        + BAR_GAP as i32 * 2;
+    let count_width = bars
+        .iter()
+        .map(|(_, count, _)| text_size(&font, scale, &count.to_string()).0)
+        .max()
+        .unwrap_or(0)
+        + BAR_GAP as i32 * 2;
+
+    let max_bar = (CHART_WIDTH as i32 - label_width - count_width).max(1) as f64;
+    let max_count = bars.iter().map(|(_, c, _)| *c).max().unwrap_or(1).max(1) as f64;
+
+    for (i, (label, count, col)) in bars.iter().enumerate() {
+        let y = (BAR_GAP + (BAR_HEIGHT + BAR_GAP) * i as u32) as i32;
+        let text_y = y + (BAR_HEIGHT as i32 - text_size(&font, scale, label).1) / 2;
+        let bar_width = ((*count as f64 / max_count) * max_bar).round().max(1.0) as u32;
+
+        drawing::draw_text_mut(
+            &mut img,
+            Rgba([255, 255, 255, 255]),
+            BAR_GAP as i32,
+            text_y,
+            scale,
+            &font,
+            label,
+        );
+        drawing::draw_filled_rect_mut(
+            &mut img,
+            Rect::at(label_width, y).of_size(bar_width, BAR_HEIGHT),
+            Rgba([col.0, col.1, col.2, 255]),
+        );
+        drawing::draw_text_mut(
+            &mut img,
+            Rgba([255, 255, 255, 255]),
+            label_width + bar_width as i32 + BAR_GAP as i32,
+            text_y,
+            scale,
+            &font,
+            &count.to_string(),
+        );
+    }
+
+    img
+}
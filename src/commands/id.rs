@@ -2,18 +2,22 @@ use std::collections::BTreeMap;
 use std::ops::RangeInclusive;
 
 use once_cell::sync::OnceCell;
+use poise::serenity::utils::Color;
 use poise::serenity_prelude::ButtonStyle;
 use tokio::fs;
 
+use crate::codec::{
+    self, CraftedItem, CraftedKind, DecodedItem, GearIds, GearItem, END_CHAR, OFFSET, SEPARATOR,
+    START_CHAR,
+};
 use crate::error::create_error_msg;
-use crate::wynn::items::{Identification, ItemList, Powders, StatusType, IDGROUPS};
+use crate::wynn::color;
+use crate::wynn::items::{
+    DamageTypes, DefenseTypes, Identification, Item, ItemList, Powders, Requirements, StatusType,
+    IDGROUPS,
+};
 use crate::{Context, Error, gen_embed_footer};
 
-const START_CHAR: char = '󵿰';
-const END_CHAR: char = '󵿱';
-const SEPARATOR: char = '󵿲';
-
-const OFFSET: i32 = 0xF5000;
 const EARTH: &str = "<:earth:899381388762025984>";
 const THUNDER: &str = "<:thunder:899382018452889610>";
 const WATER: &str = "<:water:899382254948737077>";
@@ -22,6 +26,19 @@ const AIR: &str = "<:air:899382632532570123>";
 
 static ITEMDB: OnceCell<ItemList> = OnceCell::new();
 
+/// Get the list of all items and cache them after the first time
+async fn get_itemlist() -> Result<&'static ItemList, Error> {
+    if let Some(db) = ITEMDB.get() {
+        Ok(db)
+    } else {
+        let itemlist: ItemList =
+            serde_json::from_slice(&fs::read("./resources/item_list.json").await?)?;
+        // another task may have loaded the list while we were reading it
+        let _ = ITEMDB.set(itemlist);
+        Ok(ITEMDB.get().unwrap())
+    }
+}
+
 /// Read wynntils id strings
 #[poise::command(prefix_command, slash_command)]
 pub async fn id(
@@ -30,46 +47,28 @@ pub async fn id(
     #[description = "Wynntils id string"]
     id_string: String,
 ) -> Result<(), Error> {
-    // read and parse the input string
-    let mut temp = id_string
-        .trim_start_matches(START_CHAR)
-        .trim_end_matches(END_CHAR)
-        .split_terminator(SEPARATOR);
-
-    let name = if let Some(v) = temp.next() {
-        v.to_string()
-    } else {
-        create_error_msg(ctx, "Invalid id string", "the given string is invalid").await;
-        return Ok(());
+    // detect the format of the string and decode it
+    let decoded = match codec::decode(&id_string) {
+        Ok(d) => d,
+        Err(why) => {
+            create_error_msg(ctx, "Invalid id string", &why.to_string()).await;
+            return Ok(());
+        }
     };
-    let ids = if let Some(v) = temp.next() {
-        v
-    } else {
-        create_error_msg(ctx, "Invalid id string", "the given string is invalid").await;
-        return Ok(());
-    }; // https://github.com/Wynntils/Wynntils/blob/development/src/main/java/com/wynntils/modules/utilities/managers/ChatItemManager.java
-    let powders = temp.next();
 
-    // Read rerolls either from the id section or the powder section
-    let rerolls = if let Some(powders) = powders {
-        powders.chars().last().unwrap() as i32 - OFFSET
-    } else {
-        ids.chars().last().unwrap() as i32 - OFFSET
-    };
+    match decoded {
+        DecodedItem::Gear(gear) => gear_item(ctx, gear).await,
+        DecodedItem::Crafted(crafted) => crafted_item(ctx, crafted).await,
+    }
+}
 
-    // get the list of all items and cache them after the first time
-    let itemlist = if let Some(db) = ITEMDB.get() {
-        db
-    } else {
-        let itemlist: ItemList =
-            serde_json::from_slice(&fs::read("./resources/item_list.json").await?)?;
-        ITEMDB.set(itemlist).unwrap();
-        ITEMDB.get().unwrap()
-    };
+/// Render a gear item using the stats from the item database
+async fn gear_item(ctx: Context<'_>, gear: GearItem) -> Result<(), Error> {
+    let itemlist = get_itemlist().await?;
     let items = &itemlist.items;
 
     // find the item and make sure it exists
-    let item = if let Some(item) = items.iter().find(|f| f.displayName == name) {
+    let item = if let Some(item) = items.iter().find(|f| f.displayName == gear.name) {
         item
     } else {
         create_error_msg(
@@ -81,35 +80,236 @@ pub async fn id(
         return Ok(());
     };
 
-    // Parse powders
-    let mut powdercount = 0;
-    let mut parsedpowders = Vec::new();
-    if item.powderAmount > 0 {
-        if let Some(powders) = powders {
-            let mut powders: Vec<i32> = powders.chars().map(|c| c as i32 - OFFSET).collect();
-            // Remove the reroll char from powder chars
-            powders.pop();
-            powders.reverse();
-
-            for mut p in powders.into_iter() {
-                while p > 0 {
-                    parsedpowders.push(Powders::from_i32(p % 6 - 1));
-                    powdercount += 1;
-
-                    p /= 6;
+    let mut desc = String::new();
+
+    push_stats(
+        &mut desc,
+        item.get_speed(),
+        item.damageTypes.as_ref(),
+        item.defenseTypes.as_ref(),
+        &item.requirements,
+    );
+
+    // sort ids so their read correctly
+    let finalids = sorted_ids(itemlist, item);
+
+    // ids
+    let mut ididx: usize = 0;
+    let mut lastgroup: Option<RangeInclusive<i32>> = Option::None;
+    let mut idprosentit: Vec<f64> = Vec::new();
+    for (ord, id) in finalids.iter() {
+        if let Some(group) = &lastgroup {
+            if !group.contains(ord) {
+                desc.push('\n')
+            }
+        }
+
+        let end = id_suffix(id.idtype);
+
+        if id.fixed || (-1 <= id.baseval && id.baseval <= 1) {
+            desc.push_str(&format!(
+                "{}{} {}\n",
+                formatnum(id.baseval),
+                end,
+                id.id.name()
+            ));
+        } else {
+            let value = match &gear.ids {
+                GearIds::Legacy(ids) => {
+                    let idstats = if let Some(v) = ids.get(ididx) {
+                        *v
+                    } else {
+                        create_error_msg(ctx, "Invalid id string", "the given string is invalid")
+                            .await;
+                        return Ok(());
+                    };
+                    ididx += 1;
+                    let encodedval = idstats / 4;
+
+                    // wynntils api sux
+                    // https://github.com/Wynntils/Wynntils/blob/development/src/main/java/com/wynntils/webapi/profiles/item/objects/IdentificationContainer.java#L38
+                    // https://github.com/Wynntils/Wynntils/blob/development/src/main/java/com/wynntils/modules/utilities/managers/ChatItemManager.java#L267
+                    if i32::abs(id.baseval) > 100 {
+                        f64::round(((encodedval as f64 + 30.0) / 100.0) * id.baseval as f64) as i32
+                    } else {
+                        encodedval + id.min_id()
+                    }
                 }
+                GearIds::Rolls(rolls) => {
+                    if let Some(roll) = rolls.get(&id.id) {
+                        f64::round(id.baseval as f64 * *roll as f64 / 100.0) as i32
+                    } else {
+                        create_error_msg(
+                            ctx,
+                            "Invalid id string",
+                            &format!("the given string is missing the {} id", id.id.name()),
+                        )
+                        .await;
+                        return Ok(());
+                    }
+                }
+            };
+
+            let prosentti = get_percent(value, id, &itemlist.identificationOrder.inverted);
+
+            desc.push_str(&format!(
+                "{}{} {} [{:.3}%]\n",
+                formatnum(value),
+                end,
+                id.id.name(),
+                prosentti
+            ));
+            idprosentit.push(prosentti);
+        }
+        for group in IDGROUPS {
+            if group.contains(ord) {
+                lastgroup = Some(group);
             }
         }
     }
-    parsedpowders.reverse();
 
+    desc.push('\n');
+
+    push_powders(&mut desc, &gear.powders, item.powderAmount);
+
+    // Footer with ids
+    if gear.rerolls != 0 {
+        desc.push_str(&format!(
+            "{} {} [{}]",
+            item.get_rarity(),
+            item.get_type(),
+            gear.rerolls
+        ));
+    } else {
+        desc.push_str(&format!("{} {}", item.get_rarity(), item.get_type()));
+    }
+
+    // make item name with id % if needed
+    let mut itemname = item.displayName.clone();
+    if !idprosentit.is_empty() {
+        itemname.push_str(&format!(
+            " [{:.3}%]",
+            idprosentit.iter().sum::<f64>() / idprosentit.len() as f64
+        ))
+    }
+
+    send_item_embed(
+        ctx,
+        item.get_color(),
+        itemname,
+        desc,
+        Some(&item.displayName),
+    )
+    .await
+}
+
+/// Render a crafted item, these carry all of their stats in the id string
+async fn crafted_item(ctx: Context<'_>, item: CraftedItem) -> Result<(), Error> {
     let mut desc = String::new();
 
-    if let Some(speed) = item.get_speed() {
+    // crafted items store their damages as ranges so convert them into the api format
+    let range = |d: Option<(i32, i32)>| d.map(|(min, max)| format!("{}-{}", min, max));
+    let damages = if item.damages.iter().any(|d| d.is_some()) {
+        Some(DamageTypes {
+            neutral: range(item.damages[0]),
+            earth: range(item.damages[1]),
+            thunder: range(item.damages[2]),
+            water: range(item.damages[3]),
+            fire: range(item.damages[4]),
+            air: range(item.damages[5]),
+        })
+    } else {
+        None
+    };
+    let defences = if item.health.is_some() || item.defences.iter().any(|d| d.is_some()) {
+        Some(DefenseTypes {
+            health: item.health,
+            earth: item.defences[0],
+            thunder: item.defences[1],
+            water: item.defences[2],
+            fire: item.defences[3],
+            air: item.defences[4],
+        })
+    } else {
+        None
+    };
+    let requirements = Requirements {
+        level: Some(item.level),
+        strength: Some(item.skills[0]),
+        dexterity: Some(item.skills[1]),
+        intelligence: Some(item.skills[2]),
+        defense: Some(item.skills[3]),
+        agility: Some(item.skills[4]),
+    };
+
+    push_stats(
+        &mut desc,
+        item.attack_speed.map(|s| s.name().to_string()),
+        damages.as_ref(),
+        defences.as_ref(),
+        &requirements,
+    );
+
+    // crafted ids roll between their min and max values
+    let itemlist = get_itemlist().await?;
+    let order = &itemlist.identificationOrder.order;
+    let mut ids: Vec<_> = item.ids.iter().collect();
+    ids.sort_by_key(|(id, _)| order.get(*id).copied().unwrap_or(i32::MAX));
+
+    for (id, (min, max)) in ids {
+        let end = id_suffix(id.status_type());
+        if min == max {
+            desc.push_str(&format!("{}{} {}\n", formatnum(*min), end, id.name()));
+        } else {
+            desc.push_str(&format!(
+                "{}{} to {}{} {}\n",
+                formatnum(*min),
+                end,
+                formatnum(*max),
+                end,
+                id.name()
+            ));
+        }
+    }
+    if !item.ids.is_empty() {
+        desc.push('\n');
+    }
+
+    if let CraftedKind::Gear(_) = item.kind {
+        push_powders(&mut desc, &item.powders, item.powder_slots);
+    }
+
+    if let Some((current, max)) = item.durability {
+        desc.push_str(&format!("Durability: [{}/{}]\n", current, max));
+    }
+    if let Some((current, max)) = item.uses {
+        desc.push_str(&format!("Charges: [{}/{}]\n", current, max));
+    }
+
+    let kind = match &item.kind {
+        CraftedKind::Gear(t) => t.name(),
+        CraftedKind::Potion => "Potion",
+        CraftedKind::Food => "Food",
+        CraftedKind::Scroll => "Scroll",
+    };
+    desc.push_str(&format!("Crafted {}", kind));
+
+    send_item_embed(ctx, color::CRAFTED_ITEM, item.name, desc, None).await
+}
+
+/// Adds the attack speed, damages, defences and requirements of an item to the description
+fn push_stats(
+    desc: &mut String,
+    speed: Option<String>,
+    damages: Option<&DamageTypes>,
+    defenses: Option<&DefenseTypes>,
+    requirements: &Requirements,
+) {
+    if let Some(speed) = speed {
         desc.push_str(&format!("{} Attack Speed\n\n", speed));
     }
 
-    if let Some(damages) = &item.damageTypes {
+    if let Some(damages) = damages {
         if let Some(d) = &damages.neutral {
             desc.push_str(&format!("Neutral Damage: {}\n", d));
         }
@@ -129,7 +329,7 @@ pub async fn id(
             desc.push_str(&format!("{} Earth Damage: {}\n", EARTH, d));
         }
     }
-    if let Some(defenses) = &item.defenseTypes {
+    if let Some(defenses) = defenses {
         if let Some(d) = &defenses.health {
             desc.push_str(&format!("❤ Health: {}\n", d));
         }
@@ -152,135 +352,46 @@ pub async fn id(
     desc.push('\n');
 
     // requirements
-    {
-        let requirements = &item.requirements;
-        if let Some(d) = requirements.level {
-            if d != 0 {
-                desc.push_str(&format!("Combat Lv. Min: {}\n", d));
-            }
-        }
-        if let Some(d) = requirements.strength {
-            if d != 0 {
-                desc.push_str(&format!("Strength Min: {}\n", d));
-            }
-        }
-        if let Some(d) = requirements.dexterity {
-            if d != 0 {
-                desc.push_str(&format!("Dexterity Min: {}\n", d));
-            }
-        }
-        if let Some(d) = requirements.intelligence {
-            if d != 0 {
-                desc.push_str(&format!("Intelligence Min: {}\n", d));
-            }
+    if let Some(d) = requirements.level {
+        if d != 0 {
+            desc.push_str(&format!("Combat Lv. Min: {}\n", d));
         }
-        if let Some(d) = requirements.defense {
-            if d != 0 {
-                desc.push_str(&format!("Defence Min: {}\n", d));
-            }
-        }
-        if let Some(d) = requirements.agility {
-            if d != 0 {
-                desc.push_str(&format!("Agility Min: {}\n", d));
-            }
+    }
+    if let Some(d) = requirements.strength {
+        if d != 0 {
+            desc.push_str(&format!("Strength Min: {}\n", d));
         }
-        desc.push('\n');
     }
-
-    // decode the id chars into numbers
-    let ids: Vec<i32> = ids.chars().map(|c| c as i32 - OFFSET).collect();
-
-    // sort ids so their read correctly
-    let mut finalids = BTreeMap::new();
-    for (id, ord) in itemlist.identificationOrder.order.iter() {
-        if let Some(sid) = item.statuses.get(id) {
-            finalids.insert(
-                ord,
-                Id {
-                    id: *id,
-                    idtype: sid.r#type,
-                    fixed: sid.isFixed,
-                    baseval: sid.baseValue,
-                },
-            );
+    if let Some(d) = requirements.dexterity {
+        if d != 0 {
+            desc.push_str(&format!("Dexterity Min: {}\n", d));
         }
     }
-
-    // ids
-    let mut ididx: i32 = 0;
-    let mut lastgroup: Option<RangeInclusive<i32>> = Option::None;
-    let mut idprosentit: Vec<f64> = Vec::new();
-    for (ord, id) in finalids.iter() {
-        if let Some(group) = &lastgroup {
-            if !group.contains(ord) {
-                desc.push('\n')
-            }
+    if let Some(d) = requirements.intelligence {
+        if d != 0 {
+            desc.push_str(&format!("Intelligence Min: {}\n", d));
         }
-
-        let end = match id.idtype {
-            StatusType::PERCENTAGE => "%",
-            StatusType::INTEGER => "",
-            StatusType::TIER => "",
-            StatusType::FOUR_SECONDS => "/4s",
-            StatusType::THREE_SECONDS => "/3s",
-        };
-
-        if id.fixed || (-1 <= id.baseval && id.baseval <= 1) {
-            desc.push_str(&format!(
-                "{}{} {}\n",
-                formatnum(id.baseval),
-                end,
-                id.id.name()
-            ));
-            ididx -= 1;
-        } else {
-            let idstats = ids[ididx as usize];
-            let encodedval = idstats / 4;
-            let value;
-            let prosentti;
-
-            // wynntils api sux
-            // https://github.com/Wynntils/Wynntils/blob/development/src/main/java/com/wynntils/webapi/profiles/item/objects/IdentificationContainer.java#L38
-            // https://github.com/Wynntils/Wynntils/blob/development/src/main/java/com/wynntils/modules/utilities/managers/ChatItemManager.java#L267
-            if i32::abs(id.baseval) > 100 {
-                value = f64::round(((encodedval as f64 + 30.0) / 100.0) * id.baseval as f64) as i32
-            } else {
-                value = encodedval + id.min_id();
-            }
-
-            prosentti = get_percent(value, id, &itemlist.identificationOrder.inverted);
-
-            if id.fixed || (-1 <= id.baseval && id.baseval <= 1) {
-                desc.push_str(&format!("{}{} {}\n", formatnum(value), end, id.id.name()));
-            } else {
-                desc.push_str(&format!(
-                    "{}{} {} [{:.3}%]\n",
-                    formatnum(value),
-                    end,
-                    id.id.name(),
-                    prosentti
-                ));
-                idprosentit.push(prosentti);
-            }
+    }
+    if let Some(d) = requirements.defense {
+        if d != 0 {
+            desc.push_str(&format!("Defence Min: {}\n", d));
         }
-        ididx += 1;
-        for group in IDGROUPS {
-            if group.contains(ord) {
-                lastgroup = Some(group);
-            }
+    }
+    if let Some(d) = requirements.agility {
+        if d != 0 {
+            desc.push_str(&format!("Agility Min: {}\n", d));
         }
     }
-
     desc.push('\n');
+}
 
-    desc.push_str(&format!(
-        "[{}/{}] Powder Slots",
-        powdercount, item.powderAmount
-    ));
+/// Adds the powder slots and powders of an item to the description
+fn push_powders(desc: &mut String, powders: &[Powders], slots: u8) {
+    desc.push_str(&format!("[{}/{}] Powder Slots", powders.len(), slots));
 
-    if powdercount != 0 {
+    if !powders.is_empty() {
         desc.push('[');
-        for p in parsedpowders {
+        for p in powders {
             match p {
                 Powders::EARTH => desc.push_str(EARTH),
                 Powders::THUNDER => desc.push_str(THUNDER),
@@ -292,59 +403,78 @@ pub async fn id(
         desc.push(']');
     }
     desc.push('\n');
+}
 
-    // Footer with ids
-    if rerolls != 0 {
-        desc.push_str(&format!(
-            "{} {} [{}]",
-            item.get_rarity(),
-            item.get_type(),
-            rerolls
-        ));
-    } else {
-        desc.push_str(&format!("{} {}", item.get_rarity(), item.get_type()));
-    }
-
-    // make item name with id % if needed
-    let mut itemname = item.displayName.clone();
-    if !idprosentit.is_empty() {
-        itemname.push_str(&format!(
-            " [{:.3}%]",
-            idprosentit.iter().sum::<f64>() / idprosentit.len() as f64
-        ))
+/// Get the suffix shown after the value of an id
+fn id_suffix(idtype: StatusType) -> &'static str {
+    match idtype {
+        StatusType::PERCENTAGE => "%",
+        StatusType::INTEGER => "",
+        StatusType::TIER => "",
+        StatusType::FOUR_SECONDS => "/4s",
+        StatusType::THREE_SECONDS => "/3s",
     }
+}
 
-    // send final message
+/// Send the final item embed, items from the database also get a wynnbuilder link
+async fn send_item_embed(
+    ctx: Context<'_>,
+    color: Color,
+    title: String,
+    desc: String,
+    wynnbuilder: Option<&str>,
+) -> Result<(), Error> {
     ctx.send(|m| {
         m.embed(|e| {
-            e.color(item.get_color());
-            e.title(itemname);
+            e.color(color);
+            e.title(title);
             e.description(desc);
             gen_embed_footer(e, &ctx.data().config.bot.name);
             e
         });
-        m.components(|c| {
-            c.create_action_row(|ar| {
-                ar.create_button(|b| {
-                    b.style(ButtonStyle::Link);
-                    b.label("Open item on Wynnbuilder");
-                    b.url(format!(
-                        "https://wynnbuilder.github.io/item.html#{}",
-                        &item.displayName.replace(" ", "%20")
-                    ));
-                    b.disabled(false);
-                    b
+        if let Some(name) = wynnbuilder {
+            m.components(|c| {
+                c.create_action_row(|ar| {
+                    ar.create_button(|b| {
+                        b.style(ButtonStyle::Link);
+                        b.label("Open item on Wynnbuilder");
+                        b.url(format!(
+                            "https://wynnbuilder.github.io/item.html#{}",
+                            name.replace(" ", "%20")
+                        ));
+                        b.disabled(false);
+                        b
+                    });
+                    ar
                 });
-                ar
+                c
             });
-            c
-        });
+        }
         m
     })
     .await?;
     Ok(())
 }
 
+/// Get the ids of an item sorted by the identification order
+fn sorted_ids<'a>(itemlist: &'a ItemList, item: &Item) -> BTreeMap<&'a i32, Id> {
+    let mut finalids = BTreeMap::new();
+    for (id, ord) in itemlist.identificationOrder.order.iter() {
+        if let Some(sid) = item.statuses.get(id) {
+            finalids.insert(
+                ord,
+                Id {
+                    id: *id,
+                    idtype: sid.r#type,
+                    fixed: sid.isFixed,
+                    baseval: sid.baseValue,
+                },
+            );
+        }
+    }
+    finalids
+}
+
 struct Id {
     id: Identification,
     idtype: StatusType,
@@ -357,20 +487,42 @@ impl Id {
         if self.fixed || (-1 <= self.baseval && self.baseval <= 1) {
             self.baseval
         } else if self.baseval < 1 {
-            f64::ceil(self.baseval as f64 * 0.7) as i32
+            self.roll(0.7)
         } else {
-            f64::floor(self.baseval as f64 * 1.3) as i32
+            self.roll(1.3)
         }
     }
     fn min_id(&self) -> i32 {
         if self.fixed || (-1 <= self.baseval && self.baseval <= 1) {
             self.baseval
         } else if self.baseval < 1 {
-            f64::ceil(self.baseval as f64 * 1.3) as i32
+            self.roll(1.3)
         } else {
-            f64::floor(self.baseval as f64 * 0.3) as i32
+            self.roll(0.3)
         }
     }
+    /// Value of the id when rolled at a multiple of the base value
+    ///
+    /// Rolls are rounded towards zero
+    fn roll(&self, multiplier: f64) -> i32 {
+        let value = self.baseval as f64 * multiplier;
+        if self.baseval < 0 {
+            f64::ceil(value) as i32
+        } else {
+            f64::floor(value) as i32
+        }
+    }
+    /// All of the values the id can roll, every value in the list is equally likely
+    ///
+    /// Positive ids roll between 30% and 130% of the base value and negative ones between 70% and 130%
+    fn possible_values(&self) -> Vec<i32> {
+        if self.fixed || (-1 <= self.baseval && self.baseval <= 1) {
+            return vec![self.baseval];
+        }
+
+        let rolls = if self.baseval > 0 { 30..=130 } else { 70..=130 };
+        rolls.map(|r| self.roll(r as f64 / 100.0)).collect()
+    }
 }
 
 fn formatnum(num: i32) -> String {
@@ -414,15 +566,7 @@ pub async fn maxid(
         return Ok(());
     };
 
-    // get the list of all items and cache them after the first time
-    let itemlist = if let Some(db) = ITEMDB.get() {
-        db
-    } else {
-        let itemlist: ItemList =
-            serde_json::from_slice(&fs::read("./resources/item_list.json").await?)?;
-        ITEMDB.set(itemlist).unwrap();
-        ITEMDB.get().unwrap()
-    };
+    let itemlist = get_itemlist().await?;
     let items = &itemlist.items;
 
     // find the item from the database
@@ -442,20 +586,7 @@ pub async fn maxid(
     };
 
     // sort ids so their read correctly
-    let mut finalids = BTreeMap::new();
-    for (id, ord) in itemlist.identificationOrder.order.iter() {
-        if let Some(sid) = item.statuses.get(id) {
-            finalids.insert(
-                ord,
-                Id {
-                    id: *id,
-                    idtype: sid.r#type,
-                    fixed: sid.isFixed,
-                    baseval: sid.baseValue,
-                },
-            );
-        }
-    }
+    let finalids = sorted_ids(itemlist, item);
 
     let mut perfids: String = String::new();
 
@@ -493,3 +624,255 @@ pub async fn maxid(
 
     Ok(())
 }
+
+/// Autocomplete item names from the item database
+async fn autocomplete_item(_ctx: Context<'_>, partial: String) -> impl Iterator<Item = String> {
+    let partial = partial.to_lowercase();
+    let names: Vec<String> = match get_itemlist().await {
+        Ok(itemlist) => itemlist
+            .items
+            .iter()
+            .filter(|i| i.displayName.to_lowercase().contains(&partial))
+            .take(25)
+            .map(|i| i.displayName.clone())
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.into_iter()
+}
+
+/// Preview the possible ids of an unidentified item
+#[poise::command(prefix_command, slash_command)]
+pub async fn unid(
+    ctx: Context<'_>,
+    #[rest]
+    #[description = "Name of the item"]
+    #[autocomplete = "autocomplete_item"]
+    item: String,
+    #[description = "Id percentage to calculate the chance of rolling above (default 80)"]
+    threshold: Option<f64>,
+    #[description = "Overall percentage to calculate the chance of rolling above (default 80)"]
+    overall: Option<f64>,
+) -> Result<(), Error> {
+    let threshold = threshold.unwrap_or(80.0).clamp(0.0, 100.0);
+    let overall = overall.unwrap_or(80.0).clamp(0.0, 100.0);
+
+    let itemlist = get_itemlist().await?;
+
+    // find the item from the database
+    let item = if let Some(item) = itemlist
+        .items
+        .iter()
+        .find(|f| f.displayName.eq_ignore_ascii_case(item.trim()))
+    {
+        item
+    } else {
+        create_error_msg(
+            ctx,
+            "Invalid item",
+            "the given item was not found in the current database",
+        )
+        .await;
+        return Ok(());
+    };
+
+    let inverted = &itemlist.identificationOrder.inverted;
+    let finalids = sorted_ids(itemlist, item);
+
+    let mut desc = String::new();
+    let mut lastgroup: Option<RangeInclusive<i32>> = Option::None;
+    // distributions of the id percentages for calculating the overall chance
+    let mut distributions = Vec::new();
+
+    for (ord, id) in finalids.iter() {
+        if let Some(group) = &lastgroup {
+            if !group.contains(ord) {
+                desc.push('\n')
+            }
+        }
+
+        let end = id_suffix(id.idtype);
+
+        if id.fixed || (-1 <= id.baseval && id.baseval <= 1) {
+            desc.push_str(&format!(
+                "{}{} {}\n",
+                formatnum(id.baseval),
+                end,
+                id.id.name()
+            ));
+        } else {
+            let values = id.possible_values();
+            let expected = values.iter().sum::<i32>() as f64 / values.len() as f64;
+
+            // percentages of every possible roll in tenths of a percent
+            let percents: Vec<usize> = values
+                .iter()
+                .map(|v| (get_percent(*v, id, inverted).clamp(0.0, 100.0) * 10.0).round() as usize)
+                .collect();
+            let above = percents
+                .iter()
+                .filter(|p| **p as f64 >= threshold * 10.0)
+                .count() as f64
+                / percents.len() as f64;
+
+            // show the ranges in the same order as they go from worst to best
+            let (low, high) = if inverted.contains(&id.id) {
+                (id.max_id(), id.min_id())
+            } else {
+                (id.min_id(), id.max_id())
+            };
+
+            desc.push_str(&format!(
+                "{}{} to {}{} {}\n> expected `{:.1}{}`, `{:.1}%` chance of ≥{}%\n",
+                formatnum(low),
+                end,
+                formatnum(high),
+                end,
+                id.id.name(),
+                expected,
+                end,
+                above * 100.0,
+                threshold
+            ));
+
+            distributions.push(percents);
+        }
+        for group in IDGROUPS {
+            if group.contains(ord) {
+                lastgroup = Some(group);
+            }
+        }
+    }
+
+    desc.push('\n');
+
+    if !distributions.is_empty() {
+        desc.push_str(&format!(
+            "Chance of an overall roll of ≥{}%: `{:.3}%`\n",
+            overall,
+            overall_chance(&distributions, overall) * 100.0
+        ));
+    }
+    desc.push_str(&format!("{} {}", item.get_rarity(), item.get_type()));
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.color(item.get_color());
+            e.title(format!("Unidentified {}", item.displayName));
+            e.description(desc);
+            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Calculates the chance that the average of the id percentages is at least the given percentage
+///
+/// Every distribution holds the equally likely percentages of one id in tenths of a percent.
+fn overall_chance(distributions: &[Vec<usize>], overall: f64) -> f64 {
+    // probabilities of every possible sum of percentages
+    let mut sums = vec![1.0];
+
+    for dist in distributions {
+        let chance = 1.0 / dist.len() as f64;
+        let mut next = vec![0.0; sums.len() + 1000];
+        for (sum, p) in sums.iter().enumerate() {
+            if *p == 0.0 {
+                continue;
+            }
+            for percent in dist {
+                next[sum + percent] += p * chance;
+            }
+        }
+        sums = next;
+    }
+
+    let wanted = (overall * 10.0 * distributions.len() as f64).round() as usize;
+    sums.iter().skip(wanted).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn id(baseval: i32) -> Id {
+        Id {
+            id: Identification::rawStrength,
+            idtype: StatusType::INTEGER,
+            fixed: false,
+            baseval,
+        }
+    }
+
+    #[test]
+    fn possible_values_match_range() {
+        for baseval in (-300..=300).filter(|b| !(-1..=1).contains(b)) {
+            let id = id(baseval);
+            let values = id.possible_values();
+            let (first, last) = (values[0], values[values.len() - 1]);
+
+            // negative ids start at their highest value because they roll from 70%
+            let (low, high) = if baseval > 0 {
+                (first, last)
+            } else {
+                (last, first)
+            };
+            assert_eq!(low, id.min_id(), "min of {}", baseval);
+            assert_eq!(high, id.max_id(), "max of {}", baseval);
+            assert!(values.iter().all(|v| (low..=high).contains(v)));
+        }
+    }
+
+    #[test]
+    fn possible_values_small() {
+        assert_eq!(id(5).min_id(), 1);
+        assert_eq!(id(5).max_id(), 6);
+        assert_eq!(id(-5).min_id(), -6);
+        assert_eq!(id(-5).max_id(), -3);
+        assert!(id(5).possible_values().contains(&1));
+        assert!(id(-5).possible_values().contains(&-3));
+    }
+
+    fn assert_chance(distributions: &[Vec<usize>], overall: f64, expected: f64) {
+        let chance = overall_chance(distributions, overall);
+        assert!(
+            (chance - expected).abs() < 1e-9,
+            "chance of {}% was {} instead of {}",
+            overall,
+            chance,
+            expected
+        );
+    }
+
+    #[test]
+    fn overall_chance_single_id() {
+        let dist = vec![vec![0, 500, 1000]];
+        assert_chance(&dist, 0.0, 1.0);
+        assert_chance(&dist, 50.0, 2.0 / 3.0);
+        assert_chance(&dist, 80.0, 1.0 / 3.0);
+        assert_chance(&dist, 100.0, 1.0 / 3.0);
+    }
+
+    #[test]
+    fn overall_chance_uniform_id() {
+        // every tenth of a percent is equally likely
+        let dist = vec![(0..=1000).collect::<Vec<usize>>()];
+        assert_chance(&dist, 80.0, 201.0 / 1001.0);
+    }
+
+    #[test]
+    fn overall_chance_two_ids() {
+        // both ids have to roll 100% to average 100%, either one does for 50%
+        let dist = vec![vec![0, 1000], vec![0, 1000]];
+        assert_chance(&dist, 50.0, 3.0 / 4.0);
+        assert_chance(&dist, 100.0, 1.0 / 4.0);
+
+        // averaging 75% needs a sum of 1500, only 1000 + 500 and 1000 + 1000 get there
+        let dist = vec![vec![0, 1000], vec![0, 500, 1000]];
+        assert_chance(&dist, 75.0, 2.0 / 6.0);
+    }
+}
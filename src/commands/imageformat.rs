@@ -0,0 +1,45 @@
+use crate::encoder::ImageFormat;
+use crate::{gen_embed_footer, Context, Error};
+
+/// Set the format images are uploaded in on this server
+#[poise::command(
+    slash_command,
+    prefix_command,
+    guild_only,
+    required_permissions = "ADMINISTRATOR"
+)]
+pub async fn imageformat(
+    ctx: Context<'_>,
+    #[description = "Format to upload images in, leave empty to use the bot's default"]
+    format: Option<ImageFormat>,
+) -> Result<(), Error> {
+    let guild = ctx.guild_id().ok_or("This command only works in servers")?;
+
+    ctx.data()
+        .settings
+        .update(guild.0, |s| s.image_format = format)
+        .await?;
+
+    let desc = match format {
+        Some(format) => format!(
+            "Images on this server will be uploaded as {}",
+            format.name()
+        ),
+        None => format!(
+            "Images on this server will be uploaded in the default format ({})",
+            ctx.data().config.image.format.name()
+        ),
+    };
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title("Image format");
+            e.description(desc);
+            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e
+        })
+    })
+    .await?;
+
+    Ok(())
+}
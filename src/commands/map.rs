@@ -1,108 +1,461 @@
 use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{HashMap, HashSet};
 use std::error::Error;
+use std::hash::{Hash, Hasher};
 
-use crc32fast::Hasher;
-use image::{DynamicImage, Rgba};
+use image::imageops::{self, FilterType};
+use image::{ImageBuffer, Rgba};
 use imageproc::drawing;
 use imageproc::rect::Rect;
-use once_cell::sync::OnceCell;
-use rusttype::{Font, Scale};
+use rusttype::{point, Font, Scale};
 
 use poise::serenity_prelude::AttachmentType;
 
-use crate::{Context, gen_embed_footer};
-
-use image::io::Reader as ImageReader;
+use crate::commands::mapview::MapView;
+use crate::encoder::{encode, pick_format, upload_limit, ImageFormat};
+use crate::error::create_error_msg;
+use crate::palette::guild_colors;
+use crate::render::render;
+use crate::svg::render_svg;
+use crate::{gen_embed_footer_note, Context};
 
 use tracing::info;
 
-use crate::wynn::world::Territories;
+use crate::basemap::{load_image, Projection};
+use crate::config::BaseMapConfig;
+use crate::wynn::world::{get_trading_routes, Territories, Territory, TerritoryLocation};
 use cached::proc_macro::cached;
 
-/// Static for the image file so we don't have to load it every time
-static MAPBASE: OnceCell<image::ImageBuffer<Rgba<u8>, Vec<u8>>> = OnceCell::new();
+/// Padding added around cropped regions in blocks
+pub const REGION_PADDING: f64 = 150.0;
 
-/// Helper function for getting the base map
-fn get_mapbase() -> Result<image::ImageBuffer<Rgba<u8>, Vec<u8>>, Box<dyn Error + Send + Sync>> {
-    let out = if let Some(pm) = MAPBASE.get() {
-        pm.clone()
-    } else {
-        let map: image::ImageBuffer<Rgba<u8>, Vec<u8>> = if let DynamicImage::ImageRgba8(img) =
-            ImageReader::open("./resources/main-map.png")?.decode()?
-        {
-            img
-        } else {
-            panic!("main-map.png invalid!!!");
-        };
-        MAPBASE.set(map.clone()).unwrap();
-        map
-    };
-    Ok(out)
+/// Size that cropped images are scaled to fit by default
+const AUTO_ZOOM_SIZE: f64 = 1000.0;
+
+/// Largest allowed zoom factor
+const MAX_ZOOM: f64 = 4.0;
+
+/// Most pixels a zoomed image may have, larger crops get less zoom
+const MAX_ZOOMED_PIXELS: f64 = 8_000_000.0;
+
+/// Get the font used for drawing text on maps
+pub fn get_font() -> Font<'static> {
+    let font_data: &[u8] = include_bytes!("../../resources/Roboto-Bold.ttf");
+    Font::try_from_bytes(font_data).unwrap()
 }
 
 // allow getting a new map every 30s otherwise use a cached one
 #[cached(time = 30, result = true)]
-async fn get_map() -> Result<Territories, reqwest::Error> {
+pub async fn get_map() -> Result<Territories, reqwest::Error> {
     info!("Getting new map data from wynntils");
     let client = crate::get_reqwest_client()?;
 
-    let terrs: Territories = client
+    let mut terrs: Territories = client
         .get("https://athena.wynntils.com/cache/get/territoryList")
         .send()
         .await?
         .json()
         .await?;
 
+    if let Some(routes) = get_trading_routes().await {
+        terrs.fill_trading_routes(routes);
+    }
+
     Ok(terrs)
 }
 
 /// Render the wynncraft guild map
 #[poise::command(prefix_command, slash_command, track_edits)]
-pub async fn map(ctx: Context<'_>) -> Result<(), crate::Error> {
+pub async fn map(
+    ctx: Context<'_>,
+    #[description = "Territory to zoom in on"]
+    #[autocomplete = "autocomplete_territory"]
+    territory: Option<String>,
+    #[description = "Number of nearby territories to include with the territory"]
+    neighbours: Option<usize>,
+    #[description = "Name or prefix of a guild to highlight and zoom in on"] guild: Option<String>,
+    #[description = "Area to zoom in on as `x1 z1 x2 z2`"] coords: Option<String>,
+    #[description = "Zoom factor for the cropped area"] zoom: Option<f64>,
+    #[description = "Draw the trading routes between territories"] routes: Option<RouteMode>,
+    #[description = "Draw a legend of the guilds holding territories (default: true)"]
+    legend: Option<bool>,
+    #[description = "Base map to draw on"]
+    #[autocomplete = "autocomplete_basemap"]
+    basemap: Option<String>,
+    #[description = "Format of the image"] format: Option<MapFormat>,
+) -> Result<(), crate::Error> {
+    let base = if let Some(b) = ctx.data().config.base_map(basemap.as_deref()) {
+        b
+    } else {
+        unknown_basemap(ctx).await;
+        return Ok(());
+    };
+
+    if routes.is_some() && get_trading_routes().await.is_none() {
+        create_error_msg(
+            ctx,
+            "Trading routes unavailable",
+            "The trading routes could not be loaded, try again later",
+        )
+        .await;
+        return Ok(());
+    }
+
     // defer here so we can respond with an image and discord knows that it might take a while before we respond
     ctx.defer().await?;
 
+    let limit = upload_limit(ctx);
+
     // load territory data from wynntils api
     let terrs = get_map().await?;
 
+    // figure out which part of the map we want
+    let region = if let Some(name) = territory {
+        let terr = if let Some(t) = find_territory(&terrs, &name) {
+            t
+        } else {
+            create_error_msg(
+                ctx,
+                "Territory not found",
+                &format!("No territory called `{}` exists", name),
+            )
+            .await;
+            return Ok(());
+        };
+
+        let mut region = Region::around(&terr.location);
+        let (cx, cz) = terr.location.center();
+
+        // include the closest territories
+        let mut others: Vec<&TerritoryLocation> = terrs
+            .territories
+            .values()
+            .filter(|t| t.territory != terr.territory)
+            .map(|t| &t.location)
+            .collect();
+        others.sort_by(|a, b| {
+            let da = distance(a.center(), (cx, cz));
+            let db = distance(b.center(), (cx, cz));
+            da.partial_cmp(&db).unwrap()
+        });
+        for loc in others.into_iter().take(neighbours.unwrap_or(0)) {
+            region.include(loc);
+        }
+
+        Some(region)
+    } else if let Some(name) = &guild {
+        let mut region: Option<Region> = None;
+        for terr in terrs.territories.values() {
+            if terr.held_by(name) {
+                if let Some(r) = &mut region {
+                    r.include(&terr.location);
+                } else {
+                    region = Some(Region::around(&terr.location));
+                }
+            }
+        }
+
+        if region.is_none() {
+            create_error_msg(
+                ctx,
+                "Guild not found",
+                &format!("No guild called `{}` currently holds any territories", name),
+            )
+            .await;
+            return Ok(());
+        }
+        region
+    } else if let Some(coords) = coords {
+        if let Some(region) = Region::parse(&coords) {
+            Some(region)
+        } else {
+            create_error_msg(
+                ctx,
+                "Invalid coordinates",
+                "Coordinates should be given as `x1 z1 x2 z2`",
+            )
+            .await;
+            return Ok(());
+        }
+    } else {
+        None
+    };
+
+    let options = MapOptions {
+        highlight: guild.clone(),
+        routes,
+    };
+
+    // describe the highlighted guild's holdings
+    let caption = guild.as_deref().and_then(|g| guild_caption(&terrs, g));
+
+    let format = match format {
+        Some(MapFormat::Svg) => {
+            return send_svg(
+                ctx,
+                terrs,
+                base.clone(),
+                options,
+                region,
+                zoom,
+                caption,
+                limit,
+            )
+            .await
+        }
+        format => pick_format(ctx, format.and_then(MapFormat::image_format)).await,
+    };
+
+    let legend = legend.unwrap_or(true);
+    let legend_entries = ctx.data().config.image.legend_entries;
+    let quality = ctx.data().config.image.webp_quality;
+
+    // the buttons start from the same part of the map
+    let view = MapView::new(base, basemap, region, guild.clone(), routes, legend, format).await?;
+
+    // without a region the reply starts out zoomed in on the view the buttons move around
+    let crop = match region {
+        Some(region) => Some(region.pad(REGION_PADDING)),
+        None => view.region(),
+    };
+
+    // everything that changes the image goes into the cache key
+    let mut hasher = DefaultHasher::new();
+    hash_territories(&terrs, &mut hasher);
+    options.hash(&mut hasher);
+    base.image.hash(&mut hasher);
+    crop.hash(&mut hasher);
+    zoom.map(f64::to_bits).hash(&mut hasher);
+    legend.hash(&mut hasher);
+    legend_entries.hash(&mut hasher);
+    quality.to_bits().hash(&mut hasher);
+    format.hash(&mut hasher);
+    limit.hash(&mut hasher);
+
+    let base = base.clone();
+    let encoded = ctx
+        .data()
+        .render_cache
+        .get_or_render(hasher.finish(), || {
+            render(ctx, move || {
+                let mut img = render_map(&terrs, &base, &options)?;
+
+                if let Some(crop) = crop {
+                    img = crop_region(&img, &base.projection, crop, zoom);
+                }
+
+                // the legend is drawn after cropping so it is always in view
+                if legend {
+                    draw_legend(&mut img, &terrs, legend_entries)?;
+                }
+
+                encode(img, format, quality, limit)
+            })
+        })
+        .await?;
+
+    let filename = encoded.filename("map");
+
+    // serenity wants a cow for whatever reason
+    let cow = Cow::from(encoded.data.as_slice());
+
+    // construct reply message
+    ctx.send(|m| {
+        m.embed(|e| {
+            if let Some((title, desc)) = caption {
+                e.title(title);
+                e.description(desc);
+            }
+            e.image(format!("attachment://{}", filename));
+            gen_embed_footer_note(e, &ctx.data().config.bot.name, &encoded.describe());
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: cow,
+            filename: filename.clone(),
+        });
+        if view.fits() {
+            m.components(|c| view.buttons(c));
+        }
+        m
+    })
+    .await?;
+
+    // processingmsg.delete(&ctx.http).await?;
+
+    Ok(())
+}
+
+/// Send the map as an svg document
+#[allow(clippy::too_many_arguments)]
+async fn send_svg(
+    ctx: Context<'_>,
+    terrs: Territories,
+    base: BaseMapConfig,
+    options: MapOptions,
+    region: Option<Region>,
+    zoom: Option<f64>,
+    caption: Option<(String, String)>,
+    limit: usize,
+) -> Result<(), crate::Error> {
+    let territories = terrs.territories.len();
+    let svg = render(ctx, move || {
+        render_svg(
+            &terrs,
+            &base,
+            &options,
+            region.map(|r| r.pad(REGION_PADDING)),
+            zoom,
+        )
+    })
+    .await?;
+
+    if svg.len() > limit {
+        create_error_msg(
+            ctx,
+            "Map too big",
+            "The svg map is too big to upload, set a url for the base map in the config so it doesn't have to be embedded",
+        )
+        .await;
+        return Ok(());
+    }
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            match caption {
+                Some((title, desc)) => {
+                    e.title(title);
+                    e.description(desc);
+                }
+                None => {
+                    e.title("Territory map");
+                    e.description(format!("Vector map of {} territories", territories));
+                }
+            }
+            gen_embed_footer_note(e, &ctx.data().config.bot.name, "svg");
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: Cow::from(svg.into_bytes()),
+            filename: String::from("map.svg"),
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Format the map is sent in
+///
+/// Either one of the image formats or an svg document that can be edited.
+#[derive(poise::SlashChoiceParameter, Clone, Copy, PartialEq)]
+pub enum MapFormat {
+    #[name = "png"]
+    Png,
+    #[name = "webp (lossless)"]
+    WebpLossless,
+    #[name = "webp"]
+    Webp,
+    #[name = "svg"]
+    Svg,
+}
+
+impl MapFormat {
+    /// The image format, `None` for svg
+    fn image_format(self) -> Option<ImageFormat> {
+        match self {
+            Self::Png => Some(ImageFormat::Png),
+            Self::WebpLossless => Some(ImageFormat::WebpLossless),
+            Self::Webp => Some(ImageFormat::Webp),
+            Self::Svg => None,
+        }
+    }
+}
+
+/// How trading routes should be drawn on the map
+#[derive(poise::SlashChoiceParameter, Clone, Copy, PartialEq, Hash)]
+pub enum RouteMode {
+    #[name = "all"]
+    All,
+    #[name = "by guild"]
+    ByGuild,
+}
+
+/// Options for how the map should be drawn
+#[derive(Clone, Default, Hash)]
+pub struct MapOptions {
+    /// Name or prefix of a guild whose territories are drawn in colour while the others are greyed out
+    pub highlight: Option<String>,
+    /// Whether and how to draw the trading routes
+    pub routes: Option<RouteMode>,
+}
+
+/// Largest font size of territory labels
+pub const MAX_LABEL_SIZE: f32 = 40.0;
+/// Smallest font size of territory labels, smaller labels are left out
+const MIN_LABEL_SIZE: f32 = 8.0;
+/// Space kept between a label and the edges of its territory in pixels
+const LABEL_MARGIN: i32 = 2;
+
+/// Color of trading routes when they aren't coloured by guild
+const ROUTE_COLOR: Rgba<u8> = Rgba([255, 255, 255, 200]);
+/// Color of trading routes between territories of the same guild
+const ROUTE_OWN_COLOR: Rgba<u8> = Rgba([80, 255, 80, 230]);
+/// Color of trading routes between territories of different guilds
+const ROUTE_HOSTILE_COLOR: Rgba<u8> = Rgba([255, 80, 80, 230]);
+
+/// Draws all of the territories on top of the base map
+pub fn render_map(
+    terrs: &Territories,
+    base: &BaseMapConfig,
+    options: &MapOptions,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, Box<dyn Error + Send + Sync>> {
+    let colors = guild_colors(terrs)?;
+
+    render_territories(terrs, base, options.routes, |terr| {
+        let col = colors[&terr.guild];
+
+        // grey out everyone other than the highlighted guild
+        match &options.highlight {
+            Some(guild) if !terr.held_by(guild) => desaturate(col),
+            _ => col,
+        }
+    })
+}
+
+/// Draws all of the territories on top of the base map in the colours picked by `color`
+pub fn render_territories<F>(
+    terrs: &Territories,
+    base: &BaseMapConfig,
+    routes: Option<RouteMode>,
+    color: F,
+) -> Result<ImageBuffer<Rgba<u8>, Vec<u8>>, Box<dyn Error + Send + Sync>>
+where
+    F: Fn(&Territory) -> (u8, u8, u8),
+{
+    let proj = &base.projection;
+
     // ouput image
-    let mut out = drawing::Blend(get_mapbase()?);
+    let mut out = drawing::Blend(load_image(&base.image)?.as_ref().clone());
 
     // name rendering stuff
-    let font_data: &[u8] = include_bytes!("../../resources/Roboto-Bold.ttf");
-    let font: Font<'static> = Font::try_from_bytes(font_data).unwrap();
+    let font = get_font();
 
     // go thru all territories and render the rects for them
     for (_, terr) in terrs.territories.iter() {
         let loc = &terr.location;
 
         // widths
-        let width = (f64::abs(loc.endX - loc.startX) / 3.0) as u32;
-        let height = (f64::abs(loc.endZ - loc.startZ) / 3.0) as u32;
+        let width = proj.length(f64::abs(loc.endX - loc.startX)) as u32;
+        let height = proj.length(f64::abs(loc.endZ - loc.startZ)) as u32;
 
         // position calculations
-        let x = if loc.startX < loc.endX {
-            calc_x(loc.startX) as i32
-        } else {
-            calc_x(loc.endX) as i32
-        };
-        let y = if loc.startZ < loc.endZ {
-            calc_z(loc.startZ) as i32
-        } else {
-            calc_z(loc.endZ) as i32
-        };
+        let x = proj.x(loc.min_x()) as i32;
+        let y = proj.z(loc.min_z()) as i32;
 
-        // guild color calculations
-        let col = if let Some(col) = &terr.guildColor {
-            if !col.is_empty() {
-                let col = hex::decode(col[1..].to_owned())?;
-                (col[0], col[1], col[2])
-            } else {
-                guild_color(terr.guild.clone())
-            }
-        } else {
-            guild_color(terr.guild.clone())
-        };
+        let col = color(terr);
 
         let fillcol = Rgba([col.0, col.1, col.2, 127]);
         let edgecol = Rgba([col.0, col.1, col.2, 255]);
@@ -111,73 +464,641 @@ pub async fn map(ctx: Context<'_>) -> Result<(), crate::Error> {
 
         drawing::draw_filled_rect_mut(&mut out, area, fillcol);
         drawing::draw_hollow_rect_mut(&mut out, area, edgecol);
+    }
+
+    // trading routes go between the rects and the names so both stay visible
+    if let Some(mode) = routes {
+        draw_routes(&mut out, proj, terrs, mode);
+    }
+
+    draw_labels(&mut out, proj, terrs, &font);
+
+    Ok(out.0)
+}
+
+/// Draws the guild prefixes centred in their territories
+///
+/// Bigger territories are labelled first and labels that would overlap an already drawn label or
+/// don't fit in their territory are left out, so a cluster of small territories ends up sharing
+/// the label of its biggest one.
+fn draw_labels(
+    img: &mut drawing::Blend<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    proj: &Projection,
+    terrs: &Territories,
+    font: &Font,
+) {
+    let mut sorted: Vec<&Territory> = terrs.territories.values().collect();
+    sorted.sort_by(|a, b| {
+        let area = |t: &Territory| {
+            (t.location.max_x() - t.location.min_x()) * (t.location.max_z() - t.location.min_z())
+        };
+        area(b)
+            .partial_cmp(&area(a))
+            .unwrap_or(std::cmp::Ordering::Equal)
+            .then_with(|| a.territory.cmp(&b.territory))
+    });
+
+    let mut placed: Vec<Rect> = Vec::new();
+
+    for terr in sorted {
+        if terr.guildPrefix.is_empty() {
+            continue;
+        }
+
+        let loc = &terr.location;
+
+        let width = proj.length(f64::abs(loc.endX - loc.startX)) as i32;
+        let height = proj.length(f64::abs(loc.endZ - loc.startZ)) as i32;
+
+        let x = proj.x(loc.min_x()) as i32;
+        let y = proj.z(loc.min_z()) as i32;
+
+        let (scale, bounds) = if let Some(fit) = fit_label(
+            font,
+            &terr.guildPrefix,
+            width - LABEL_MARGIN * 2,
+            height - LABEL_MARGIN * 2,
+        ) {
+            fit
+        } else {
+            continue;
+        };
 
-        // maybe find a nice way to get a good color for this
-        let textcol = Rgba([255, 255, 255, 255]);
+        // position of the text origin so the glyphs end up in the middle of the territory
+        let text_x = x + (width - bounds.width()) / 2 - bounds.min.x;
+        let text_y = y + (height - bounds.height()) / 2 - bounds.min.y;
+
+        // area covered by the label including the outline
+        let area = Rect::at(text_x + bounds.min.x - 1, text_y + bounds.min.y - 1)
+            .of_size(bounds.width() as u32 + 2, bounds.height() as u32 + 2);
+
+        if placed.iter().any(|p| p.intersect(area).is_some()) {
+            continue;
+        }
+
+        let (textcol, outlinecol) = label_colors(average_color(&img.0, area));
+
+        for (dx, dy) in [(-1, -1), (1, -1), (-1, 1), (1, 1)] {
+            drawing::draw_text_mut(
+                img,
+                outlinecol,
+                text_x + dx,
+                text_y + dy,
+                scale,
+                font,
+                &terr.guildPrefix,
+            );
+        }
+        drawing::draw_text_mut(img, textcol, text_x, text_y, scale, font, &terr.guildPrefix);
+
+        placed.push(area);
+    }
+}
+
+/// Find the biggest font size at which a label fits in the given space
+///
+/// Returns the scale and the pixel bounds of the glyphs or `None` if the label would be too small
+/// to read.
+fn fit_label(
+    font: &Font,
+    text: &str,
+    max_width: i32,
+    max_height: i32,
+) -> Option<(Scale, rusttype::Rect<i32>)> {
+    let mut size = (max_height as f32).min(MAX_LABEL_SIZE);
+
+    while size >= MIN_LABEL_SIZE {
+        let scale = Scale::uniform(size);
+        let bounds = text_bounds(font, scale, text)?;
+        if bounds.width() <= max_width && bounds.height() <= max_height {
+            return Some((scale, bounds));
+        }
+        size *= 0.9;
+    }
+
+    None
+}
+
+/// Average colour of the pixels in an area of the image
+fn average_color(img: &ImageBuffer<Rgba<u8>, Vec<u8>>, area: Rect) -> (u8, u8, u8) {
+    let x1 = area.left().clamp(0, img.width() as i32) as u32;
+    let y1 = area.top().clamp(0, img.height() as i32) as u32;
+    let x2 = (area.right() + 1).clamp(0, img.width() as i32) as u32;
+    let y2 = (area.bottom() + 1).clamp(0, img.height() as i32) as u32;
+
+    let mut sum = [0u64; 3];
+    let mut count = 0;
+    for y in y1..y2 {
+        for x in x1..x2 {
+            let px = img.get_pixel(x, y);
+            for (s, c) in sum.iter_mut().zip(px.0) {
+                *s += c as u64;
+            }
+            count += 1;
+        }
+    }
+
+    if count == 0 {
+        return (0, 0, 0);
+    }
+    (
+        (sum[0] / count) as u8,
+        (sum[1] / count) as u8,
+        (sum[2] / count) as u8,
+    )
+}
+
+/// Relative luminance of a colour as defined by WCAG
+fn luminance(col: (u8, u8, u8)) -> f64 {
+    let channel = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(col.0) + 0.7152 * channel(col.1) + 0.0722 * channel(col.2)
+}
+
+/// Text and outline colour for a label on the given background
+///
+/// Picks whichever of black and white has the higher contrast ratio with the background.
+fn label_colors(background: (u8, u8, u8)) -> (Rgba<u8>, Rgba<u8>) {
+    let lum = luminance(background);
+    let white_contrast = 1.05 / (lum + 0.05);
+    let black_contrast = (lum + 0.05) / 0.05;
+
+    if white_contrast >= black_contrast {
+        (Rgba([255, 255, 255, 255]), Rgba([0, 0, 0, 160]))
+    } else {
+        (Rgba([0, 0, 0, 255]), Rgba([255, 255, 255, 160]))
+    }
+}
+
+/// Draws a line between the centres of every pair of territories connected by a trading route
+fn draw_routes(
+    img: &mut drawing::Blend<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    proj: &Projection,
+    terrs: &Territories,
+    mode: RouteMode,
+) {
+    for (terr, other) in trading_routes(terrs) {
+        let color = route_color(mode, terr, other);
+
+        let (x1, y1) = proj.to_pixel(terr.location.center());
+        let (x2, y2) = proj.to_pixel(other.location.center());
+
+        drawing::draw_line_segment_mut(img, (x1 as f32, y1 as f32), (x2 as f32, y2 as f32), color);
+    }
+}
+
+/// Every trading route between two existing territories, once
+///
+/// Routes go both ways but aren't always listed on both of their territories, so they are
+/// collected as unordered pairs. The result is sorted by name to keep the output stable.
+pub fn trading_routes(terrs: &Territories) -> Vec<(&Territory, &Territory)> {
+    let mut pairs: HashSet<(&str, &str)> = HashSet::new();
+    for terr in terrs.territories.values() {
+        for other in &terr.tradingRoutes {
+            let (a, b) = (terr.territory.as_str(), other.as_str());
+            if a != b {
+                pairs.insert((a.min(b), a.max(b)));
+            }
+        }
+    }
+
+    let mut pairs: Vec<(&str, &str)> = pairs.into_iter().collect();
+    pairs.sort_unstable();
+
+    pairs
+        .into_iter()
+        .filter_map(|(a, b)| Some((terrs.territories.get(a)?, terrs.territories.get(b)?)))
+        .collect()
+}
+
+/// Colour of the trading route between two territories
+pub fn route_color(mode: RouteMode, a: &Territory, b: &Territory) -> Rgba<u8> {
+    match mode {
+        RouteMode::All => ROUTE_COLOR,
+        RouteMode::ByGuild if a.guild == b.guild => ROUTE_OWN_COLOR,
+        RouteMode::ByGuild => ROUTE_HOSTILE_COLOR,
+    }
+}
+
+/// Draws a panel in the top left corner listing the guilds with the most territories
+///
+/// At most `max_entries` guilds are listed, the rest are summarised in a final line.
+pub fn draw_legend(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    terrs: &Territories,
+    max_entries: usize,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    if max_entries == 0 {
+        return Ok(());
+    }
+
+    // count the territories of each guild
+    let mut guilds: HashMap<&str, (&Territory, usize)> = HashMap::new();
+    for terr in terrs.territories.values() {
+        guilds.entry(&terr.guild).or_insert((terr, 0)).1 += 1;
+    }
+
+    let mut guilds: Vec<(&Territory, usize)> = guilds.into_values().collect();
+    guilds.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.guild.cmp(&b.0.guild)));
+
+    let colors = guild_colors(terrs)?;
+
+    let mut lines = Vec::new();
+    for (terr, count) in guilds.iter().take(max_entries) {
+        lines.push((
+            Some(colors[&terr.guild]),
+            format!("{} [{}] - {}", terr.guild, terr.guildPrefix, count),
+        ));
+    }
+    if guilds.len() > max_entries {
+        let rest: usize = guilds[max_entries..].iter().map(|(_, c)| c).sum();
+        lines.push((
+            None,
+            format!("{} more guilds - {}", guilds.len() - max_entries, rest),
+        ));
+    }
+
+    draw_legend_panel(img, &lines);
+
+    Ok(())
+}
+
+/// Draws a panel in the top left corner with a line of text for every entry
+///
+/// Entries with a colour get a swatch of it in front of the text.
+pub fn draw_legend_panel(
+    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
+    lines: &[(Option<(u8, u8, u8)>, String)],
+) {
+    // scale the text with the image so the legend stays readable on big maps
+    let font = get_font();
+    let size = (img.width() as f32 / 60.0).clamp(14.0, 32.0);
+    let scale = Scale::uniform(size);
+    let line_height = (size * 1.3) as i32;
+    let swatch = (size * 0.8) as u32;
+    let margin = (size / 2.0) as i32;
+
+    let text_width = lines
+        .iter()
+        .map(|(_, text)| text_size(&font, scale, text).0)
+        .max()
+        .unwrap_or(0);
+
+    let mut out = drawing::Blend(std::mem::take(img));
+
+    drawing::draw_filled_rect_mut(
+        &mut out,
+        Rect::at(margin, margin).of_size(
+            (text_width + swatch as i32 + margin * 3) as u32,
+            (line_height * lines.len() as i32 + margin * 2) as u32,
+        ),
+        Rgba([0, 0, 0, 180]),
+    );
+
+    for (i, (col, text)) in lines.iter().enumerate() {
+        let y = margin * 2 + line_height * i as i32;
+        let text_x = margin * 3 + swatch as i32;
+
+        if let Some(col) = col {
+            // centre the swatch on the text
+            let swatch_y = y + (line_height - swatch as i32) / 2 - margin / 4;
+            drawing::draw_filled_rect_mut(
+                &mut out,
+                Rect::at(margin * 2, swatch_y).of_size(swatch, swatch),
+                Rgba([col.0, col.1, col.2, 255]),
+            );
+        }
 
         drawing::draw_text_mut(
             &mut out,
-            textcol,
-            x + 3,
-            y + 3,
-            Scale::uniform((width as f32 / 2.5).min(height as f32 / 1.5)),
+            Rgba([255, 255, 255, 255]),
+            text_x,
+            y,
+            scale,
             &font,
-            &terr.guildPrefix,
+            text,
         );
     }
 
-    // encode image as webp of quality 80
-    let img_data: Vec<u8>;
+    *img = out.0;
+}
 
-    {
-        let img = &DynamicImage::ImageRgba8(out.0);
+/// Pixel bounds of the glyphs in a line of text drawn at the origin
+///
+/// Returns `None` if none of the characters have any visible pixels.
+pub fn text_bounds(font: &Font, scale: Scale, text: &str) -> Option<rusttype::Rect<i32>> {
+    let v_metrics = font.v_metrics(scale);
+    font.layout(text, scale, point(0.0, v_metrics.ascent))
+        .filter_map(|g| g.pixel_bounding_box())
+        .reduce(|a, b| rusttype::Rect {
+            min: point(a.min.x.min(b.min.x), a.min.y.min(b.min.y)),
+            max: point(a.max.x.max(b.max.x), a.max.y.max(b.max.y)),
+        })
+}
 
-        let encoder = webp::Encoder::from_image(img)?;
-        let encoded = encoder.encode(ctx.data().config.image.webp_quality);
+/// Width and height in pixels of a line of text
+pub fn text_size(font: &Font, scale: Scale, text: &str) -> (i32, i32) {
+    let v_metrics = font.v_metrics(scale);
+    let width = font
+        .layout(text, scale, point(0.0, v_metrics.ascent))
+        .filter_map(|g| g.pixel_bounding_box())
+        .map(|bb| bb.max.x)
+        .max()
+        .unwrap_or(0);
+    (width, (v_metrics.ascent - v_metrics.descent).ceil() as i32)
+}
+
+/// Hash everything about the territories that is drawn on the map
+pub fn hash_territories<H: Hasher>(terrs: &Territories, state: &mut H) {
+    let mut sorted: Vec<&Territory> = terrs.territories.values().collect();
+    sorted.sort_by(|a, b| a.territory.cmp(&b.territory));
 
-        img_data = (*encoded).to_vec();
+    for terr in sorted {
+        terr.territory.hash(state);
+        terr.guild.hash(state);
+        terr.guildPrefix.hash(state);
+        terr.guildColor.hash(state);
+        terr.tradingRoutes.hash(state);
     }
+}
 
-    // serenity wants a cow for whatever reason
-    let cow = Cow::from(img_data);
+/// Title and description summarising the territories held by a guild
+fn guild_caption(terrs: &Territories, guild: &str) -> Option<(String, String)> {
+    let held: Vec<&Territory> = terrs
+        .territories
+        .values()
+        .filter(|t| t.held_by(guild))
+        .collect();
 
-    // construct reply message
-    ctx.send(|m| {
-        m.embed(|e| {
-            e.image("attachment://map.webp");
-            gen_embed_footer(e, &ctx.data().config.bot.name);
-            e
-        });
-        m.attachment(AttachmentType::Bytes {
-            data: cow,
-            filename: String::from("map.webp"),
-        });
-        m
-    })
-    .await?;
+    let first = held.first()?;
+    let title = format!("{} [{}]", first.guild, first.guildPrefix);
 
-    // processingmsg.delete(&ctx.http).await?;
+    let mut desc = format!("Holds {} territories", held.len());
 
-    Ok(())
+    let oldest = held
+        .iter()
+        .filter_map(|t| Some((t.acquired_time()?, t)))
+        .min_by_key(|(time, _)| *time);
+    let newest = held
+        .iter()
+        .filter_map(|t| Some((t.acquired_time()?, t)))
+        .max_by_key(|(time, _)| *time);
+
+    if let Some((time, terr)) = oldest {
+        desc.push_str(&format!(
+            "\nOldest: {} acquired <t:{}:R>",
+            terr.territory,
+            time.timestamp()
+        ));
+    }
+    if let Some((time, terr)) = newest {
+        desc.push_str(&format!(
+            "\nNewest: {} acquired <t:{}:R>",
+            terr.territory,
+            time.timestamp()
+        ));
+    }
+
+    Some((title, desc))
+}
+
+/// Turn a colour into a grey of the same brightness
+pub fn desaturate(col: (u8, u8, u8)) -> (u8, u8, u8) {
+    let luma = (0.299 * col.0 as f64 + 0.587 * col.1 as f64 + 0.114 * col.2 as f64) as u8;
+    (luma, luma, luma)
+}
+
+/// Area of the world in world coordinates
+#[derive(Clone, Copy)]
+pub struct Region {
+    min_x: f64,
+    min_z: f64,
+    max_x: f64,
+    max_z: f64,
+}
+
+impl Hash for Region {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        for n in [self.min_x, self.min_z, self.max_x, self.max_z] {
+            n.to_bits().hash(state);
+        }
+    }
 }
 
-/// Gets the guilds color from it's name
-#[cached]
-fn guild_color(name: String) -> (u8, u8, u8) {
-    // hash the guilds nane with crc32
-    let mut hasher = Hasher::new();
-    hasher.update(name.as_bytes());
-    let hash = hasher.finalize();
+impl Region {
+    /// Region covering a single territory
+    pub fn around(loc: &TerritoryLocation) -> Self {
+        Self {
+            min_x: loc.min_x(),
+            min_z: loc.min_z(),
+            max_x: loc.max_x(),
+            max_z: loc.max_z(),
+        }
+    }
+
+    /// Region covering a single point
+    pub fn point(x: f64, z: f64) -> Self {
+        Self {
+            min_x: x,
+            min_z: z,
+            max_x: x,
+            max_z: z,
+        }
+    }
 
-    let bytes: Vec<u8> = hash.to_ne_bytes().into_iter().rev().collect();
+    /// Centre point of the region as `(x, z)`
+    pub fn center(&self) -> (f64, f64) {
+        (
+            (self.min_x + self.max_x) / 2.0,
+            (self.min_z + self.max_z) / 2.0,
+        )
+    }
+
+    /// Length of the longest side of the region
+    pub fn size(&self) -> f64 {
+        (self.max_x - self.min_x).max(self.max_z - self.min_z)
+    }
+
+    /// Grow the region so that it also covers the given territory
+    pub fn include(&mut self, loc: &TerritoryLocation) {
+        self.min_x = self.min_x.min(loc.min_x());
+        self.min_z = self.min_z.min(loc.min_z());
+        self.max_x = self.max_x.max(loc.max_x());
+        self.max_z = self.max_z.max(loc.max_z());
+    }
+
+    /// Grow the region so that it also covers the given point
+    pub fn include_point(&mut self, x: f64, z: f64) {
+        self.min_x = self.min_x.min(x);
+        self.min_z = self.min_z.min(z);
+        self.max_x = self.max_x.max(x);
+        self.max_z = self.max_z.max(z);
+    }
+
+    /// Add some padding around the region
+    pub fn pad(self, amount: f64) -> Self {
+        Self {
+            min_x: self.min_x - amount,
+            min_z: self.min_z - amount,
+            max_x: self.max_x + amount,
+            max_z: self.max_z + amount,
+        }
+    }
+
+    /// Grow the region around its centre so both sides are at least `size` long
+    pub fn at_least(self, size: f64) -> Self {
+        let grow_x = ((size - (self.max_x - self.min_x)) / 2.0).max(0.0);
+        let grow_z = ((size - (self.max_z - self.min_z)) / 2.0).max(0.0);
+        Self {
+            min_x: self.min_x - grow_x,
+            min_z: self.min_z - grow_z,
+            max_x: self.max_x + grow_x,
+            max_z: self.max_z + grow_z,
+        }
+    }
+
+    /// Parse a region from a string of `x1 z1 x2 z2`
+    fn parse(s: &str) -> Option<Self> {
+        let nums: Vec<f64> = s
+            .split(|c: char| c.is_whitespace() || c == ',')
+            .filter(|p| !p.is_empty())
+            .map(|p| p.parse())
+            .collect::<Result<_, _>>()
+            .ok()?;
+
+        if nums.len() != 4 {
+            return None;
+        }
+
+        Some(Self {
+            min_x: nums[0].min(nums[2]),
+            min_z: nums[1].min(nums[3]),
+            max_x: nums[0].max(nums[2]),
+            max_z: nums[1].max(nums[3]),
+        })
+    }
+}
+
+/// Crop the rendered map to a region and scale it
+///
+/// Without an explicit zoom the crop is scaled to be roughly [`AUTO_ZOOM_SIZE`] pixels on its longest side.
+pub fn crop_region(
+    img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    proj: &Projection,
+    region: Region,
+    zoom: Option<f64>,
+) -> ImageBuffer<Rgba<u8>, Vec<u8>> {
+    let (x1, y1, width, height) = crop_bounds(img.dimensions(), proj, region);
+    let zoom = crop_zoom(zoom, width, height);
+
+    let cropped = imageops::crop_imm(img, x1, y1, width, height).to_image();
+
+    if zoom > 1.0 {
+        imageops::resize(
+            &cropped,
+            (width as f64 * zoom) as u32,
+            (height as f64 * zoom) as u32,
+            FilterType::Triangle,
+        )
+    } else {
+        cropped
+    }
+}
+
+/// Pixels of an image covered by a region as `(x, y, width, height)`
+///
+/// The area is kept inside of the image and is always at least a pixel in size.
+pub fn crop_bounds(
+    (img_width, img_height): (u32, u32),
+    proj: &Projection,
+    region: Region,
+) -> (u32, u32, u32, u32) {
+    let x1 = (proj.x(region.min_x).max(0.0) as u32).min(img_width - 1);
+    let y1 = (proj.z(region.min_z).max(0.0) as u32).min(img_height - 1);
+    let x2 = (proj.x(region.max_x).max(0.0) as u32).clamp(x1 + 1, img_width);
+    let y2 = (proj.z(region.max_z).max(0.0) as u32).clamp(y1 + 1, img_height);
+
+    (x1, y1, x2 - x1, y2 - y1)
+}
+
+/// Zoom factor for a cropped area, picked automatically if not given
+///
+/// The zoom is limited so the scaled image stays below [`MAX_ZOOMED_PIXELS`].
+pub fn crop_zoom(zoom: Option<f64>, width: u32, height: u32) -> f64 {
+    let max = (MAX_ZOOMED_PIXELS / (width as f64 * height as f64)).sqrt();
+    zoom.unwrap_or_else(|| (AUTO_ZOOM_SIZE / width.max(height) as f64).max(1.0))
+        .clamp(1.0, MAX_ZOOM.min(max).max(1.0))
+}
+
+/// Find a territory by its name ignoring case
+pub fn find_territory<'a>(terrs: &'a Territories, name: &str) -> Option<&'a Territory> {
+    terrs
+        .territories
+        .values()
+        .find(|t| t.territory.eq_ignore_ascii_case(name.trim()))
+}
+
+/// Autocomplete territory names from the current territory list
+pub async fn autocomplete_territory(
+    _ctx: Context<'_>,
+    partial: String,
+) -> impl Iterator<Item = String> {
+    let partial = partial.to_lowercase();
+    let mut names: Vec<String> = match get_map().await {
+        Ok(terrs) => terrs
+            .territories
+            .into_values()
+            .map(|t| t.territory)
+            .filter(|t| t.to_lowercase().contains(&partial))
+            .collect(),
+        Err(_) => Vec::new(),
+    };
+    names.sort_unstable();
+    names.truncate(25);
+    names.into_iter()
+}
 
-    (bytes[1], bytes[2], bytes[3])
+/// Autocomplete the names of the configured base maps
+pub async fn autocomplete_basemap(
+    ctx: Context<'_>,
+    partial: String,
+) -> impl Iterator<Item = String> {
+    let partial = partial.to_lowercase();
+    ctx.data()
+        .config
+        .maps
+        .keys()
+        .filter(|n| n.to_lowercase().contains(&partial))
+        .take(25)
+        .cloned()
+        .collect::<Vec<_>>()
+        .into_iter()
 }
 
-fn calc_x(x: f64) -> f64 {
-    (x / 3.0) - 566.333 + 1364.0
+/// Tell the user that the base map they asked for doesn't exist
+pub async fn unknown_basemap(ctx: Context<'_>) {
+    let names: Vec<String> = ctx
+        .data()
+        .config
+        .maps
+        .keys()
+        .map(|n| format!("`{}`", n))
+        .collect();
+    create_error_msg(
+        ctx,
+        "Unknown base map",
+        &format!("Available base maps are: {}", names.join(", ")),
+    )
+    .await;
 }
 
-fn calc_z(z: f64) -> f64 {
-    (z / 3.0) + 41.0 + 2162.0
+/// Distance between two points
+fn distance(a: (f64, f64), b: (f64, f64)) -> f64 {
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2)).sqrt()
 }
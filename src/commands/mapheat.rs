@@ -0,0 +1,182 @@
+use std::borrow::Cow;
+
+use poise::serenity_prelude::AttachmentType;
+
+use crate::commands::map::{
+    autocomplete_basemap, draw_legend_panel, get_map, render_territories, unknown_basemap,
+};
+use crate::commands::warhistory::MAX_DAYS;
+use crate::encoder::{encode, pick_format, upload_limit, ImageFormat};
+use crate::error::create_error_msg;
+use crate::render::render;
+use crate::{gen_embed_footer_note, Context, Error};
+
+/// Colours of the heat ramp from no changes to the most changes
+const RAMP: [(u8, u8, u8); 5] = [
+    (49, 54, 149),
+    (116, 173, 209),
+    (254, 224, 144),
+    (244, 109, 67),
+    (165, 0, 38),
+];
+
+/// Number of steps of the ramp shown in the legend
+const LEGEND_STEPS: usize = 5;
+
+/// Number of territories listed as the most contested
+const HOTTEST: usize = 5;
+
+/// Colour the map by how often each territory changed hands
+#[poise::command(prefix_command, slash_command)]
+pub async fn mapheat(
+    ctx: Context<'_>,
+    #[description = "Number of days to look back"] days: u32,
+    #[description = "Base map to draw on"]
+    #[autocomplete = "autocomplete_basemap"]
+    basemap: Option<String>,
+    #[description = "Format of the image"] format: Option<ImageFormat>,
+) -> Result<(), Error> {
+    let history = if let Some(h) = &ctx.data().history {
+        h
+    } else {
+        create_error_msg(
+            ctx,
+            "War history unavailable",
+            "Territory changes are not being recorded on this bot",
+        )
+        .await;
+        return Ok(());
+    };
+
+    let base = if let Some(b) = ctx.data().config.base_map(basemap.as_deref()) {
+        b.clone()
+    } else {
+        unknown_basemap(ctx).await;
+        return Ok(());
+    };
+
+    // defer here so we can respond with an image and discord knows that it might take a while before we respond
+    ctx.defer().await?;
+
+    let days = days.clamp(1, MAX_DAYS);
+    let since = chrono::Utc::now().timestamp() - days as i64 * 86400;
+
+    let terrs = get_map().await?;
+    let mut counts = history.change_counts(since).await?;
+    // territories that have been removed from the game can't be drawn
+    counts.retain(|t, _| terrs.territories.contains_key(t));
+
+    let max = counts.values().copied().max().unwrap_or(0);
+    if max == 0 {
+        create_error_msg(
+            ctx,
+            "No wars found",
+            &format!(
+                "No territory changes were recorded in the last {} days",
+                days
+            ),
+        )
+        .await;
+        return Ok(());
+    }
+
+    let mut hottest: Vec<(&String, &i64)> = counts.iter().collect();
+    hottest.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+
+    let mut desc = format!(
+        "{} changes over {} territories\n\n**Most contested**\n",
+        counts.values().sum::<i64>(),
+        counts.len()
+    );
+    for (terr, n) in hottest.iter().take(HOTTEST) {
+        desc.push_str(&format!("{} ({} changes)\n", terr, n));
+    }
+
+    let format = pick_format(ctx, format).await;
+    let limit = upload_limit(ctx);
+    let quality = ctx.data().config.image.webp_quality;
+
+    let encoded = render(ctx, move || {
+        let mut img = render_territories(&terrs, &base, None, |terr| {
+            heat_color(counts.get(&terr.territory).copied().unwrap_or(0), max)
+        })?;
+
+        draw_legend_panel(&mut img, &legend_lines(days, max));
+
+        encode(img, format, quality, limit)
+    })
+    .await?;
+
+    let filename = encoded.filename("mapheat");
+
+    // serenity wants a cow for whatever reason
+    let cow = Cow::from(encoded.data.as_slice());
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title(format!("Territory changes in the last {} days", days));
+            e.description(desc);
+            e.image(format!("attachment://{}", filename));
+            gen_embed_footer_note(e, &ctx.data().config.bot.name, &encoded.describe());
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: cow,
+            filename: filename.clone(),
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Position of a count on the ramp between 0 and 1
+///
+/// The scale is logarithmic so a few very busy territories don't wash out the rest.
+fn heat(count: i64, max: i64) -> f64 {
+    if max <= 0 {
+        return 0.0;
+    }
+    ((count as f64).ln_1p() / (max as f64).ln_1p()).clamp(0.0, 1.0)
+}
+
+/// Inverse of [`heat`], the count at a position on the ramp
+fn count_at(heat: f64, max: i64) -> i64 {
+    (heat * (max as f64).ln_1p()).exp_m1().round() as i64
+}
+
+/// Colour of a territory that changed hands `count` times
+fn heat_color(count: i64, max: i64) -> (u8, u8, u8) {
+    ramp(heat(count, max))
+}
+
+/// Colour at a position between 0 and 1 on the ramp
+fn ramp(t: f64) -> (u8, u8, u8) {
+    let pos = t.clamp(0.0, 1.0) * (RAMP.len() - 1) as f64;
+    let i = (pos.floor() as usize).min(RAMP.len() - 2);
+    let frac = pos - i as f64;
+
+    let (a, b) = (RAMP[i], RAMP[i + 1]);
+    let lerp = |a: u8, b: u8| (a as f64 + (b as f64 - a as f64) * frac).round() as u8;
+
+    (lerp(a.0, b.0), lerp(a.1, b.1), lerp(a.2, b.2))
+}
+
+/// Lines of the legend explaining the colours
+fn legend_lines(days: u32, max: i64) -> Vec<(Option<(u8, u8, u8)>, String)> {
+    let mut lines = vec![(None, format!("Changes in {} days", days))];
+
+    let mut shown = Vec::new();
+    for step in 0..LEGEND_STEPS {
+        let count = count_at(step as f64 / (LEGEND_STEPS - 1) as f64, max);
+        // small maximums give the same count more than once
+        if shown.contains(&count) {
+            continue;
+        }
+        shown.push(count);
+        lines.push((Some(heat_color(count, max)), count.to_string()));
+    }
+
+    lines
+}
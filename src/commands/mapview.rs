@@ -0,0 +1,414 @@
+//! Map views that can be moved around and zoomed with buttons
+//!
+//! Everything needed to render a view is stored in the custom ids of its buttons, so a button
+//! press can be handled without keeping any state around between presses.
+
+use std::borrow::Cow;
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+
+use poise::serenity::builder::CreateComponents;
+use poise::serenity::model::interactions::message_component::{
+    ButtonStyle, MessageComponentInteraction,
+};
+use poise::serenity::model::interactions::InteractionResponseType;
+use poise::serenity_prelude::{self as serenity, AttachmentType, Message};
+use tracing::warn;
+
+use crate::basemap::image_dimensions;
+use crate::commands::map::{
+    crop_region, draw_legend, get_map, hash_territories, render_map, MapOptions, Region, RouteMode,
+    REGION_PADDING,
+};
+use crate::config::BaseMapConfig;
+use crate::encoder::{encode, Encoded, ImageFormat, DEFAULT_UPLOAD_LIMIT};
+use crate::wynn::world::Territories;
+use crate::{gen_embed_footer_note, Data, Error};
+
+/// Start of the custom id of every map button
+pub const BUTTON_PREFIX: &str = "map:";
+
+/// Discord doesn't allow custom ids longer than this
+const MAX_ID_LENGTH: usize = 100;
+
+/// Width of the area shown at zoom level 1 in blocks, every level after that halves it
+const LEVEL_ONE_SPAN: f64 = 4096.0;
+
+/// Highest zoom level
+const MAX_LEVEL: u8 = 5;
+
+/// What a button does to the view
+#[derive(Clone, Copy, PartialEq)]
+enum Action {
+    Left,
+    Up,
+    Down,
+    Right,
+    ZoomIn,
+    ZoomOut,
+}
+
+impl Action {
+    /// Short code of the action used in custom ids
+    fn code(self) -> char {
+        match self {
+            Self::Left => 'l',
+            Self::Up => 'u',
+            Self::Down => 'd',
+            Self::Right => 'r',
+            Self::ZoomIn => 'i',
+            Self::ZoomOut => 'o',
+        }
+    }
+
+    fn from_code(code: &str) -> Option<Self> {
+        match code {
+            "l" => Some(Self::Left),
+            "u" => Some(Self::Up),
+            "d" => Some(Self::Down),
+            "r" => Some(Self::Right),
+            "i" => Some(Self::ZoomIn),
+            "o" => Some(Self::ZoomOut),
+            _ => None,
+        }
+    }
+
+    /// Text on the button
+    fn label(self) -> &'static str {
+        match self {
+            Self::Left => "◀",
+            Self::Up => "▲",
+            Self::Down => "▼",
+            Self::Right => "▶",
+            Self::ZoomIn => "Zoom in",
+            Self::ZoomOut => "Zoom out",
+        }
+    }
+}
+
+/// Part of the map shown in a message along with the options it is drawn with
+#[derive(Clone)]
+pub struct MapView {
+    /// Centre of the view in world coordinates
+    x: i32,
+    z: i32,
+    /// 0 shows the whole map, every level above that zooms in twice as far
+    level: u8,
+    routes: Option<RouteMode>,
+    legend: bool,
+    format: ImageFormat,
+    /// Name of the base map, empty for the default one
+    basemap: String,
+    /// Name or prefix of the highlighted guild
+    guild: Option<String>,
+}
+
+impl MapView {
+    /// View covering the given region or the middle of the map at the first zoom level
+    pub async fn new(
+        base: &BaseMapConfig,
+        basemap: Option<String>,
+        region: Option<Region>,
+        guild: Option<String>,
+        routes: Option<RouteMode>,
+        legend: bool,
+        format: ImageFormat,
+    ) -> Result<Self, Error> {
+        let ((x, z), level) = match region {
+            Some(region) => {
+                let size = region.size() + REGION_PADDING * 2.0;
+                // zoom in as far as possible while still showing all of the region
+                let level = (1..=MAX_LEVEL)
+                    .rev()
+                    .find(|l| span(*l) >= size)
+                    .unwrap_or(1);
+                (region.center(), level)
+            }
+            None => {
+                let (width, height) = image_dimensions(&base.image).await?;
+                let center = base
+                    .projection
+                    .to_world((width as f64 / 2.0, height as f64 / 2.0));
+                (center, 1)
+            }
+        };
+
+        Ok(Self {
+            x: x.round() as i32,
+            z: z.round() as i32,
+            level,
+            routes,
+            legend,
+            format,
+            basemap: basemap.unwrap_or_default(),
+            guild,
+        })
+    }
+
+    /// Area of the world shown, `None` for the whole map
+    pub fn region(&self) -> Option<Region> {
+        if self.level == 0 {
+            return None;
+        }
+        Some(Region::point(self.x as f64, self.z as f64).pad(span(self.level) / 2.0))
+    }
+
+    /// The view after pressing a button
+    fn apply(&self, action: Action) -> Self {
+        let mut view = self.clone();
+        // move by half of the view so there is some overlap with the previous one
+        let step = (span(self.level) / 2.0) as i32;
+
+        match action {
+            Action::Left => view.x -= step,
+            Action::Right => view.x += step,
+            Action::Up => view.z -= step,
+            Action::Down => view.z += step,
+            Action::ZoomIn => view.level = (self.level + 1).min(MAX_LEVEL),
+            Action::ZoomOut => view.level = self.level.saturating_sub(1),
+        }
+
+        view
+    }
+
+    /// Whether a button would do anything in this view
+    fn enabled(&self, action: Action) -> bool {
+        match action {
+            Action::ZoomIn => self.level < MAX_LEVEL,
+            Action::ZoomOut => self.level > 0,
+            // the whole map is already in view
+            _ => self.level > 0,
+        }
+    }
+
+    /// Custom id of the button doing the action in this view
+    ///
+    /// The format is `map:action:x:z:level:routes:legend:format:basemap:guild`, the guild goes
+    /// last so it may contain colons itself.
+    fn custom_id(&self, action: Action) -> String {
+        let routes = match self.routes {
+            None => '-',
+            Some(RouteMode::All) => 'a',
+            Some(RouteMode::ByGuild) => 'g',
+        };
+        let format = match self.format {
+            ImageFormat::Png => 'p',
+            ImageFormat::WebpLossless => 'l',
+            ImageFormat::Webp => 'w',
+        };
+
+        format!(
+            "{}{}:{}:{}:{}:{}:{}:{}:{}:{}",
+            BUTTON_PREFIX,
+            action.code(),
+            self.x,
+            self.z,
+            self.level,
+            routes,
+            self.legend as u8,
+            format,
+            self.basemap,
+            self.guild.as_deref().unwrap_or_default()
+        )
+    }
+
+    /// Read the view and the pressed button from a custom id
+    fn parse(id: &str) -> Option<(Action, Self)> {
+        let mut parts = id.strip_prefix(BUTTON_PREFIX)?.splitn(9, ':');
+
+        let action = Action::from_code(parts.next()?)?;
+        let x = parts.next()?.parse().ok()?;
+        let z = parts.next()?.parse().ok()?;
+        let level = parts.next()?.parse::<u8>().ok()?.min(MAX_LEVEL);
+        let routes = match parts.next()? {
+            "-" => None,
+            "a" => Some(RouteMode::All),
+            "g" => Some(RouteMode::ByGuild),
+            _ => return None,
+        };
+        let legend = parts.next()? == "1";
+        let format = match parts.next()? {
+            "p" => ImageFormat::Png,
+            "l" => ImageFormat::WebpLossless,
+            "w" => ImageFormat::Webp,
+            _ => return None,
+        };
+        let basemap = parts.next()?.to_string();
+        let guild = Some(parts.next()?)
+            .filter(|g| !g.is_empty())
+            .map(String::from);
+
+        Some((
+            action,
+            Self {
+                x,
+                z,
+                level,
+                routes,
+                legend,
+                format,
+                basemap,
+                guild,
+            },
+        ))
+    }
+
+    /// Whether the state fits in the custom ids of the buttons
+    ///
+    /// Views with very long guild or base map names can't be moved around.
+    pub fn fits(&self) -> bool {
+        [Action::Left, Action::ZoomOut]
+            .into_iter()
+            .all(|a| self.custom_id(a).len() <= MAX_ID_LENGTH)
+    }
+
+    /// Add the arrow and zoom buttons
+    pub fn buttons<'a>(&self, c: &'a mut CreateComponents) -> &'a mut CreateComponents {
+        let rows = [
+            [Action::Left, Action::Up, Action::Down, Action::Right].as_slice(),
+            [Action::ZoomIn, Action::ZoomOut].as_slice(),
+        ];
+
+        for row in rows {
+            c.create_action_row(|ar| {
+                for action in row {
+                    ar.create_button(|b| {
+                        b.style(ButtonStyle::Primary);
+                        b.label(action.label());
+                        b.custom_id(self.custom_id(*action));
+                        b.disabled(!self.enabled(*action));
+                        b
+                    });
+                }
+                ar
+            });
+        }
+        c
+    }
+}
+
+/// Width of the area shown at a zoom level in blocks
+fn span(level: u8) -> f64 {
+    LEVEL_ONE_SPAN / 2f64.powi(level as i32 - 1)
+}
+
+/// Render a view of the map
+fn render_view(
+    terrs: &Territories,
+    base: &BaseMapConfig,
+    view: &MapView,
+    legend_entries: usize,
+    quality: f32,
+) -> Result<Encoded, Error> {
+    let options = MapOptions {
+        highlight: view.guild.clone(),
+        routes: view.routes,
+    };
+
+    let mut img = render_map(terrs, base, &options)?;
+
+    if let Some(region) = view.region() {
+        img = crop_region(&img, &base.projection, region, None);
+    }
+
+    if view.legend {
+        draw_legend(&mut img, terrs, legend_entries)?;
+    }
+
+    // the server isn't known here so stay within the limit every server has
+    encode(img, view.format, quality, DEFAULT_UPLOAD_LIMIT)
+}
+
+/// Handle a press of one of the map buttons
+pub async fn map_interact_handler(
+    ctx: &serenity::Context,
+    data: &Data,
+    msg: &Message,
+    interact: &MessageComponentInteraction,
+) -> Result<(), Error> {
+    // rendering can take longer than discord waits for a response so respond first
+    interact
+        .create_interaction_response(&ctx, |r| {
+            r.kind(InteractionResponseType::DeferredUpdateMessage);
+            r
+        })
+        .await?;
+
+    let (action, view) = if let Some(v) = MapView::parse(&interact.data.custom_id) {
+        v
+    } else {
+        warn!("Invalid map button id `{}`", interact.data.custom_id);
+        return Ok(());
+    };
+    let view = view.apply(action);
+
+    let base = data
+        .config
+        .base_map(
+            Some(&view.basemap)
+                .filter(|b| !b.is_empty())
+                .map(|b| b.as_str()),
+        )
+        .ok_or_else(|| format!("Base map `{}` no longer exists", view.basemap))?
+        .clone();
+
+    let terrs = get_map().await?;
+    let legend_entries = data.config.image.legend_entries;
+    let quality = data.config.image.webp_quality;
+
+    let mut hasher = DefaultHasher::new();
+    "view".hash(&mut hasher);
+    hash_territories(&terrs, &mut hasher);
+    base.image.hash(&mut hasher);
+    view.custom_id(Action::ZoomIn).hash(&mut hasher);
+    legend_entries.hash(&mut hasher);
+    quality.to_bits().hash(&mut hasher);
+
+    let encoded = {
+        let view = view.clone();
+        data.render_cache
+            .get_or_render(hasher.finish(), || {
+                data.render_pool
+                    .run(move || render_view(&terrs, &base, &view, legend_entries, quality))
+            })
+            .await?
+    };
+
+    let filename = encoded.filename("map");
+    let cow = Cow::from(encoded.data.as_slice());
+
+    // keep the title and description of the original reply
+    let (title, desc) = msg
+        .embeds
+        .first()
+        .map(|e| (e.title.clone(), e.description.clone()))
+        .unwrap_or_default();
+
+    let mut message = msg.clone();
+    message
+        .edit(&ctx.http, |m| {
+            m.embed(|e| {
+                if let Some(title) = title {
+                    e.title(title);
+                }
+                if let Some(desc) = desc {
+                    e.description(desc);
+                }
+                e.image(format!("attachment://{}", filename));
+                gen_embed_footer_note(e, &data.config.bot.name, &encoded.describe());
+                e
+            });
+            for old in &msg.attachments {
+                m.remove_existing_attachment(old.id);
+            }
+            m.attachment(AttachmentType::Bytes {
+                data: cow,
+                filename: filename.clone(),
+            });
+            m.components(|c| view.buttons(c));
+            m
+        })
+        .await?;
+
+    Ok(())
+}
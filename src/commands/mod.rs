@@ -1,4 +1,13 @@
+pub mod feed;
 pub mod gather;
+pub mod guilds;
 pub mod id;
+pub mod imageformat;
 pub mod map;
+pub mod mapheat;
+pub mod mapview;
+pub mod territory;
+pub mod timelapse;
 pub mod up;
+pub mod warhistory;
+pub mod whereis;
@@ -0,0 +1,118 @@
+use std::borrow::Cow;
+
+use poise::serenity::utils::Color;
+use poise::serenity_prelude::AttachmentType;
+
+use crate::commands::map::{
+    autocomplete_territory, crop_region, find_territory, get_map, render_map, MapOptions, Region,
+    REGION_PADDING,
+};
+use crate::encoder::{encode, pick_format, upload_limit};
+use crate::error::create_error_msg;
+use crate::palette::guild_colors;
+use crate::render::render;
+use crate::util::format_duration;
+use crate::{gen_embed_footer_note, Context, Error};
+
+/// Show who holds a territory and for how long
+#[poise::command(prefix_command, slash_command)]
+pub async fn territory(
+    ctx: Context<'_>,
+    #[rest]
+    #[description = "Name of the territory"]
+    #[autocomplete = "autocomplete_territory"]
+    name: String,
+) -> Result<(), Error> {
+    // defer here so we can respond with an image and discord knows that it might take a while before we respond
+    ctx.defer().await?;
+
+    let terrs = get_map().await?;
+
+    let terr = if let Some(t) = find_territory(&terrs, &name) {
+        t
+    } else {
+        create_error_msg(
+            ctx,
+            "Territory not found",
+            &format!("No territory called `{}` exists", name),
+        )
+        .await;
+        return Ok(());
+    };
+
+    // same colour as on the map
+    let col = guild_colors(&terrs)?[&terr.guild];
+
+    let mut desc = format!(
+        "Held by **{}** [{}]\nColor: `#{}`\n",
+        terr.guild,
+        terr.guildPrefix,
+        hex::encode([col.0, col.1, col.2])
+    );
+
+    if let Some(acquired) = terr.acquired_time() {
+        let held = chrono::Utc::now() - acquired;
+        desc.push_str(&format!(
+            "Acquired <t:{}:f>\nHeld for `{}`\n",
+            acquired.timestamp(),
+            format_duration(held.num_seconds())
+        ));
+    }
+
+    if !terr.tradingRoutes.is_empty() {
+        desc.push_str(&format!(
+            "\nTrading routes: {}",
+            terr.tradingRoutes.join(", ")
+        ));
+    }
+
+    let title = terr.territory.clone();
+    let region = Region::around(&terr.location).pad(REGION_PADDING);
+
+    // small map of the area around the territory with the holder highlighted
+    let options = MapOptions {
+        highlight: Some(terr.guild.clone()),
+        ..Default::default()
+    };
+    let base = ctx
+        .data()
+        .config
+        .base_map(None)
+        .ok_or("No base maps are configured")?
+        .clone();
+    let quality = ctx.data().config.image.webp_quality;
+    let format = pick_format(ctx, None).await;
+    let limit = upload_limit(ctx);
+
+    let encoded = render(ctx, move || {
+        let img = render_map(&terrs, &base, &options)?;
+        let img = crop_region(&img, &base.projection, region, None);
+        encode(img, format, quality, limit)
+    })
+    .await?;
+
+    let filename = encoded.filename("territory");
+    let note = encoded.describe();
+
+    // serenity wants a cow for whatever reason
+    let cow = Cow::from(encoded.data);
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.color(Color::from_rgb(col.0, col.1, col.2));
+            e.title(title);
+            e.description(desc);
+            e.image(format!("attachment://{}", filename));
+            gen_embed_footer_note(e, &ctx.data().config.bot.name, &note);
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: cow,
+            filename: filename.clone(),
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
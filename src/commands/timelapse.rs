@@ -0,0 +1,274 @@
+use std::borrow::Cow;
+use std::collections::HashMap;
+
+use chrono::{NaiveDate, NaiveDateTime, TimeZone, Utc};
+use image::codecs::gif::{GifEncoder, Repeat};
+use image::imageops::{self, FilterType};
+use image::{Delay, Frame, ImageBuffer, Rgba};
+use imageproc::drawing;
+use imageproc::rect::Rect;
+use poise::serenity_prelude::AttachmentType;
+use rusttype::Scale;
+
+use crate::commands::map::{get_font, get_map, render_map, MapOptions};
+use crate::commands::warhistory::MAX_DAYS;
+use crate::encoder::upload_limit;
+use crate::error::create_error_msg;
+use crate::feed::Snapshot;
+use crate::render::render;
+use crate::wynn::world::Territories;
+use crate::{gen_embed_footer, Context, Error};
+
+/// Largest number of frames in a timelapse
+const MAX_FRAMES: usize = 40;
+
+/// Width the frames are scaled to before they are shrunk to fit the upload limit
+const FRAME_WIDTH: u32 = 800;
+
+/// How long each frame is shown in milliseconds
+const FRAME_DELAY: u32 = 600;
+
+/// Render a timelapse of the territory map from the recorded history
+#[poise::command(prefix_command, slash_command)]
+pub async fn maptimelapse(
+    ctx: Context<'_>,
+    #[description = "Start time as `YYYY-MM-DD HH:MM` (UTC), a unix timestamp or time ago like `6h`"]
+    from: String,
+    #[description = "End time in the same format, defaults to now"] to: Option<String>,
+    #[description = "Name or prefix of a guild to highlight"] guild: Option<String>,
+) -> Result<(), Error> {
+    let history = if let Some(h) = &ctx.data().history {
+        h
+    } else {
+        create_error_msg(
+            ctx,
+            "War history unavailable",
+            "Territory changes are not being recorded on this bot",
+        )
+        .await;
+        return Ok(());
+    };
+
+    let (from, to) = match (
+        parse_time(&from),
+        to.as_deref()
+            .map_or(Some(Utc::now().timestamp()), parse_time),
+    ) {
+        (Some(from), Some(to)) if from < to => (from, to),
+        _ => {
+            create_error_msg(
+                ctx,
+                "Invalid time range",
+                "Times should be given as `YYYY-MM-DD HH:MM` in UTC, a unix timestamp or as time ago like `6h` or `2d` and the start has to be before the end",
+            )
+            .await;
+            return Ok(());
+        }
+    };
+
+    if to - from > MAX_DAYS as i64 * 86400 {
+        create_error_msg(
+            ctx,
+            "Time range too long",
+            &format!("A timelapse can cover at most {} days", MAX_DAYS),
+        )
+        .await;
+        return Ok(());
+    }
+
+    // defer here so we can respond with an image and discord knows that it might take a while before we respond
+    ctx.defer().await?;
+
+    let snapshots = history.frames(from, to, MAX_FRAMES).await?;
+    if snapshots.is_empty() {
+        create_error_msg(
+            ctx,
+            "No history",
+            "No territory snapshots were recorded for the given time range",
+        )
+        .await;
+        return Ok(());
+    }
+
+    // the current territory list gives the locations and colours for the frames
+    let current = get_map().await?;
+    let base = ctx
+        .data()
+        .config
+        .base_map(None)
+        .ok_or("No base maps are configured")?
+        .clone();
+    let options = MapOptions {
+        highlight: guild,
+        ..Default::default()
+    };
+
+    let limit = upload_limit(ctx);
+
+    let (img_data, frame_count) = render(ctx, move || {
+        let font = get_font();
+        let mut frames = Vec::new();
+        for (time, snapshot) in &snapshots {
+            let terrs = apply_snapshot(&current, snapshot);
+            let img = render_map(&terrs, &base, &options)?;
+
+            let height = img.height() * FRAME_WIDTH / img.width();
+            let mut frame = drawing::Blend(imageops::resize(
+                &img,
+                FRAME_WIDTH,
+                height,
+                FilterType::Triangle,
+            ));
+
+            // label every frame with its time
+            let label = Utc
+                .timestamp_opt(*time, 0)
+                .single()
+                .map(|t| t.format("%Y-%m-%d %H:%M UTC").to_string())
+                .unwrap_or_default();
+            drawing::draw_filled_rect_mut(
+                &mut frame,
+                Rect::at(0, 0).of_size(230, 30),
+                Rgba([0, 0, 0, 180]),
+            );
+            drawing::draw_text_mut(
+                &mut frame,
+                Rgba([255, 255, 255, 255]),
+                6,
+                5,
+                Scale::uniform(20.0),
+                &font,
+                &label,
+            );
+
+            frames.push(frame.0);
+        }
+
+        // shrink the frames until the animation fits in a discord upload
+        let mut img_data = encode_gif(&frames)?;
+        while img_data.len() > limit && frames[0].width() > 200 {
+            frames = frames
+                .iter()
+                .map(|f| {
+                    imageops::resize(
+                        f,
+                        f.width() * 3 / 4,
+                        f.height() * 3 / 4,
+                        FilterType::Triangle,
+                    )
+                })
+                .collect();
+            img_data = encode_gif(&frames)?;
+        }
+
+        Ok((img_data, frames.len()))
+    })
+    .await?;
+
+    // serenity wants a cow for whatever reason
+    let cow = Cow::from(img_data);
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title("Territory timelapse");
+            e.description(format!(
+                "<t:{}:f> to <t:{}:f>\n{} frames",
+                from, to, frame_count
+            ));
+            e.image("attachment://timelapse.gif");
+            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: cow,
+            filename: String::from("timelapse.gif"),
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Parse a time given by the user into a unix timestamp
+///
+/// Times that chrono can't represent are rejected.
+fn parse_time(s: &str) -> Option<i64> {
+    let s = s.trim();
+
+    if s.eq_ignore_ascii_case("now") {
+        return Some(Utc::now().timestamp());
+    }
+
+    // time ago like 30m, 6h or 2d
+    let units = [('m', 60), ('h', 3600), ('d', 86400)];
+    for (unit, secs) in units {
+        if let Some(n) = s.strip_suffix(unit) {
+            if let Ok(n) = n.trim().parse::<i64>() {
+                return n
+                    .checked_mul(secs)
+                    .and_then(|ago| Utc::now().timestamp().checked_sub(ago))
+                    .and_then(in_range);
+            }
+        }
+    }
+
+    if let Ok(ts) = s.parse::<i64>() {
+        return in_range(ts);
+    }
+    if let Ok(t) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        return Some(t.timestamp());
+    }
+    if let Ok(d) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return Some(d.and_hms(0, 0, 0).timestamp());
+    }
+
+    None
+}
+
+/// Checks that a unix timestamp can be turned into a date
+fn in_range(ts: i64) -> Option<i64> {
+    Utc.timestamp_opt(ts, 0).single().map(|_| ts)
+}
+
+/// Recreate the territory list with the holders from a snapshot
+///
+/// Historical guild colours aren't recorded so the current colour of each guild is used.
+fn apply_snapshot(current: &Territories, snapshot: &Snapshot) -> Territories {
+    let colors: HashMap<&str, &Option<String>> = current
+        .territories
+        .values()
+        .map(|t| (t.guild.as_str(), &t.guildColor))
+        .collect();
+
+    let mut terrs = current.clone();
+    for terr in terrs.territories.values_mut() {
+        if let Some(holder) = snapshot.get(&terr.territory) {
+            if holder.guild != terr.guild {
+                terr.guildColor = colors.get(holder.guild.as_str()).and_then(|c| (*c).clone());
+                terr.guild = holder.guild.clone();
+                terr.guildPrefix = holder.prefix.clone();
+                terr.acquired = holder.acquired.clone();
+            }
+        }
+    }
+    terrs
+}
+
+/// Encode the frames into an animated gif
+fn encode_gif(frames: &[ImageBuffer<Rgba<u8>, Vec<u8>>]) -> Result<Vec<u8>, image::ImageError> {
+    let mut out = Vec::new();
+    {
+        let mut encoder = GifEncoder::new_with_speed(&mut out, 10);
+        encoder.set_repeat(Repeat::Infinite)?;
+        for f in frames {
+            encoder.encode_frame(Frame::from_parts(
+                f.clone(),
+                0,
+                0,
+                Delay::from_numer_denom_ms(FRAME_DELAY, 1),
+            ))?;
+        }
+    }
+    Ok(out)
+}
@@ -0,0 +1,90 @@
+use crate::error::create_error_msg;
+use crate::{gen_embed_footer, Context, Error};
+
+/// Longest window that can be queried in days
+pub const MAX_DAYS: u32 = 365;
+
+/// Show the territories a guild has captured and lost
+#[poise::command(prefix_command, slash_command)]
+pub async fn warhistory(
+    ctx: Context<'_>,
+    #[description = "Name or prefix of the guild"] guild: String,
+    #[description = "Number of days to look back (default 7)"] days: Option<u32>,
+) -> Result<(), Error> {
+    let history = if let Some(h) = &ctx.data().history {
+        h
+    } else {
+        create_error_msg(
+            ctx,
+            "War history unavailable",
+            "Territory changes are not being recorded on this bot",
+        )
+        .await;
+        return Ok(());
+    };
+
+    let days = days.unwrap_or(7).clamp(1, MAX_DAYS);
+    let since = chrono::Utc::now().timestamp() - days as i64 * 86400;
+
+    let hist = history.guild_history(&guild, since).await?;
+
+    if hist.captures == 0 && hist.losses == 0 {
+        create_error_msg(
+            ctx,
+            "No wars found",
+            &format!(
+                "No territory changes involving `{}` were recorded in the last {} days",
+                guild, days
+            ),
+        )
+        .await;
+        return Ok(());
+    }
+
+    let net = hist.captures - hist.losses;
+
+    let mut desc = format!(
+        "Captures: `{}`\nLosses: `{}`\nNet change: `{}{}`\n",
+        hist.captures,
+        hist.losses,
+        if net > 0 { "+" } else { "" },
+        net
+    );
+
+    if !hist.contested.is_empty() {
+        desc.push_str("\n**Most contested**\n");
+        for (terr, n) in &hist.contested {
+            desc.push_str(&format!("{} ({} changes)\n", terr, n));
+        }
+    }
+
+    if !hist.recent.is_empty() {
+        desc.push_str("\n**Latest changes**\n");
+        for c in &hist.recent {
+            if c.new_guild == c.old_guild {
+                desc.push_str(&format!(
+                    "<t:{}:R> [{}] retook {}\n",
+                    c.time, c.new_prefix, c.territory
+                ));
+            } else {
+                desc.push_str(&format!(
+                    "<t:{}:R> [{}] took {} from [{}]\n",
+                    c.time, c.new_prefix, c.territory, c.old_prefix
+                ));
+            }
+        }
+    }
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title(format!("War history of {} (last {} days)", guild, days));
+            e.description(desc);
+            gen_embed_footer(e, &ctx.data().config.bot.name);
+            e
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
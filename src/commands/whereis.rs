@@ -0,0 +1,137 @@
+use std::borrow::Cow;
+
+use image::{ImageBuffer, Rgba};
+use imageproc::drawing;
+use poise::serenity_prelude::AttachmentType;
+
+use crate::basemap::Projection;
+use crate::commands::map::{crop_region, get_map, render_map, MapOptions, Region, REGION_PADDING};
+use crate::encoder::{encode, pick_format, upload_limit};
+use crate::render::render;
+use crate::wynn::world::Territory;
+use crate::wynn::Gather::{self, Profession};
+use crate::{gen_embed_footer_note, Context, Error};
+
+/// Number of gather spots listed
+const NEAREST_SPOTS: usize = 5;
+
+/// Find out which territory a coordinate is in
+#[poise::command(prefix_command, slash_command, rename = "where")]
+pub async fn whereis(
+    ctx: Context<'_>,
+    #[description = "X coordinate"] x: f64,
+    #[description = "Z coordinate"] z: f64,
+) -> Result<(), Error> {
+    // defer here so we can respond with an image and discord knows that it might take a while before we respond
+    ctx.defer().await?;
+
+    let terrs = get_map().await?;
+
+    // territories can share edges so prefer the smallest one
+    let terr: Option<&Territory> = terrs
+        .territories
+        .values()
+        .filter(|t| t.location.contains(x, z))
+        .min_by(|a, b| {
+            area(a)
+                .partial_cmp(&area(b))
+                .unwrap_or(std::cmp::Ordering::Equal)
+        });
+
+    let mut desc = match terr {
+        Some(terr) => format!(
+            "Inside **{}**\nHeld by **{}** [{}]\n",
+            terr.territory, terr.guild, terr.guildPrefix
+        ),
+        None => String::from("Not inside of any territory\n"),
+    };
+
+    // list the closest gather spots
+    let spots = Gather::get_gatherspots().await?;
+    let mut nearest: Vec<(f64, Profession, &str)> = spots
+        .all()
+        .map(|(profession, spot)| {
+            let dist = ((spot.location.x - x).powi(2) + (spot.location.z - z).powi(2)).sqrt();
+            (dist, profession, spot.r#type.as_str())
+        })
+        .collect();
+    nearest.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(std::cmp::Ordering::Equal));
+
+    if !nearest.is_empty() {
+        desc.push_str("\nNearest gathering spots:\n");
+        for (dist, profession, kind) in nearest.iter().take(NEAREST_SPOTS) {
+            desc.push_str(&format!(
+                "{} {} `{:.0}` blocks away\n",
+                kind.to_lowercase(),
+                profession.name().to_lowercase(),
+                dist
+            ));
+        }
+    }
+
+    let base = ctx
+        .data()
+        .config
+        .base_map(None)
+        .ok_or("No base maps are configured")?
+        .clone();
+    let quality = ctx.data().config.image.webp_quality;
+    let format = pick_format(ctx, None).await;
+    let limit = upload_limit(ctx);
+
+    let encoded = render(ctx, move || {
+        let img = render_map(&terrs, &base, &MapOptions::default())?;
+
+        // mark the point
+        let mut img = drawing::Blend(img);
+        draw_marker(&mut img, &base.projection, (x, z));
+
+        let region = Region::point(x, z).pad(REGION_PADDING * 2.0);
+        let img = crop_region(&img.0, &base.projection, region, None);
+
+        encode(img, format, quality, limit)
+    })
+    .await?;
+
+    let filename = encoded.filename("where");
+    let note = encoded.describe();
+
+    // serenity wants a cow for whatever reason
+    let cow = Cow::from(encoded.data);
+
+    ctx.send(|m| {
+        m.embed(|e| {
+            e.title(format!("{:.0}, {:.0}", x, z));
+            e.description(desc);
+            e.image(format!("attachment://{}", filename));
+            gen_embed_footer_note(e, &ctx.data().config.bot.name, &note);
+            e
+        });
+        m.attachment(AttachmentType::Bytes {
+            data: cow,
+            filename: filename.clone(),
+        });
+        m
+    })
+    .await?;
+
+    Ok(())
+}
+
+/// Draws a marker on a point in the world
+pub fn draw_marker(
+    img: &mut drawing::Blend<ImageBuffer<Rgba<u8>, Vec<u8>>>,
+    proj: &Projection,
+    point: (f64, f64),
+) {
+    let (px, py) = proj.to_pixel(point);
+    let center = (px as i32, py as i32);
+    drawing::draw_filled_circle_mut(img, center, 5, Rgba([255, 255, 255, 255]));
+    drawing::draw_filled_circle_mut(img, center, 3, Rgba([255, 0, 0, 255]));
+}
+
+/// Area of a territory in blocks
+fn area(terr: &Territory) -> f64 {
+    let loc = &terr.location;
+    (loc.max_x() - loc.min_x()) * (loc.max_z() - loc.min_z())
+}
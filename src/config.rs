@@ -1,8 +1,12 @@
+use std::collections::BTreeMap;
 use std::fs;
 
 use serde::Deserialize;
 use tracing::{error, info};
 
+use crate::basemap::Projection;
+use crate::encoder::ImageFormat;
+
 fn default_true() -> bool {
     true
 }
@@ -21,6 +25,37 @@ pub struct Config {
     #[serde(default)]
     /// Config options that deal with images
     pub image: ImageConfig,
+    #[serde(default)]
+    /// Options for the territory takeover feed
+    pub feed: FeedConfig,
+    #[serde(default)]
+    /// Options for the territory war history
+    pub history: HistoryConfig,
+    #[serde(default)]
+    /// Options for the per server settings
+    pub settings: SettingsConfig,
+    #[serde(default)]
+    /// Options for the gather map
+    pub gather: GatherConfig,
+    #[serde(default = "default_maps")]
+    /// Base map images keyed by the name used to select them
+    pub maps: BTreeMap<String, BaseMapConfig>,
+}
+
+impl Config {
+    /// Get a base map by its name or the default one if no name is given
+    ///
+    /// The default is the map called `main` or the first one if there isn't one with that name.
+    pub fn base_map(&self, name: Option<&str>) -> Option<&BaseMapConfig> {
+        match name {
+            Some(name) => self
+                .maps
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+                .map(|(_, m)| m),
+            None => self.maps.get("main").or_else(|| self.maps.values().next()),
+        }
+    }
 }
 
 /// Core settings
@@ -75,14 +110,36 @@ fn default_cmd_prefix() -> String {
 /// Image encoding settings
 #[derive(Deserialize)]
 pub struct ImageConfig {
+    /// Format images are uploaded in unless a server or command picks another one
+    #[serde(default)]
+    pub format: ImageFormat,
     /// Quality of webp encoding
     #[serde(default = "default_webp_quality")]
     pub webp_quality: f32,
+    /// Most guilds listed in the map legend, 0 disables the legend
+    #[serde(default = "default_legend_entries")]
+    pub legend_entries: usize,
+    /// Memory used for caching rendered maps in megabytes, 0 disables the cache
+    #[serde(default = "default_render_cache_size")]
+    pub render_cache_size: usize,
+    /// Number of threads images are rendered on
+    #[serde(default = "default_render_threads")]
+    pub render_threads: usize,
+    /// Most renders that can wait for a free thread before new ones are turned away
+    #[serde(default = "default_render_queue")]
+    pub render_queue: usize,
 }
 
 impl Default for ImageConfig {
     fn default() -> Self {
-        Self { webp_quality: 80.0 }
+        Self {
+            format: ImageFormat::default(),
+            webp_quality: 80.0,
+            legend_entries: default_legend_entries(),
+            render_cache_size: default_render_cache_size(),
+            render_threads: default_render_threads(),
+            render_queue: default_render_queue(),
+        }
     }
 }
 
@@ -90,6 +147,162 @@ fn default_webp_quality() -> f32 {
     80.0
 }
 
+fn default_legend_entries() -> usize {
+    10
+}
+
+fn default_render_cache_size() -> usize {
+    32
+}
+
+fn default_render_threads() -> usize {
+    2
+}
+
+fn default_render_queue() -> usize {
+    10
+}
+
+/// Territory takeover feed settings
+#[derive(Deserialize)]
+pub struct FeedConfig {
+    /// whenever to post territory takeovers to subscribed channels, the territory list is still
+    /// polled for the war history when this is off
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// how often to poll the territory list in seconds, can't be less than 30
+    #[serde(default = "default_poll_interval")]
+    pub poll_interval: u64,
+    /// file where the subscriptions and the last seen territories are stored
+    #[serde(default = "default_feed_file")]
+    pub state_file: String,
+}
+
+impl Default for FeedConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            poll_interval: default_poll_interval(),
+            state_file: default_feed_file(),
+        }
+    }
+}
+
+fn default_poll_interval() -> u64 {
+    60
+}
+
+fn default_feed_file() -> String {
+    String::from("./data/feed.json")
+}
+
+/// Territory war history settings
+#[derive(Deserialize)]
+pub struct HistoryConfig {
+    /// whenever to record territory changes
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+    /// sqlite database the history is stored in
+    #[serde(default = "default_history_db")]
+    pub database: String,
+}
+
+impl Default for HistoryConfig {
+    fn default() -> Self {
+        Self {
+            enabled: true,
+            database: default_history_db(),
+        }
+    }
+}
+
+fn default_history_db() -> String {
+    String::from("./data/history.db")
+}
+
+/// Per server settings
+#[derive(Deserialize)]
+pub struct SettingsConfig {
+    /// file where the settings of every server are stored
+    #[serde(default = "default_settings_file")]
+    pub file: String,
+}
+
+impl Default for SettingsConfig {
+    fn default() -> Self {
+        Self {
+            file: default_settings_file(),
+        }
+    }
+}
+
+fn default_settings_file() -> String {
+    String::from("./data/settings.json")
+}
+
+/// Gather map settings
+#[derive(Deserialize)]
+pub struct GatherConfig {
+    /// spots of the same material this many blocks apart are grouped into one cluster
+    #[serde(default = "default_cluster_radius")]
+    pub cluster_radius: f64,
+    /// fewest spots that make a cluster, smaller groups are drawn as single spots
+    #[serde(default = "default_cluster_min_spots")]
+    pub cluster_min_spots: usize,
+}
+
+impl Default for GatherConfig {
+    fn default() -> Self {
+        Self {
+            cluster_radius: default_cluster_radius(),
+            cluster_min_spots: default_cluster_min_spots(),
+        }
+    }
+}
+
+fn default_cluster_radius() -> f64 {
+    30.0
+}
+
+fn default_cluster_min_spots() -> usize {
+    3
+}
+
+/// A base map image and its calibration
+#[derive(Deserialize, Clone)]
+pub struct BaseMapConfig {
+    /// image the territories are drawn on
+    pub image: String,
+    /// greyscale version of the image used for the gather map, the normal image is used if unset
+    pub gray_image: Option<String>,
+    /// public url of the image that svg maps link to, the image is embedded in them if unset
+    pub image_url: Option<String>,
+    /// how world coordinates line up with the image
+    #[serde(flatten)]
+    pub projection: Projection,
+}
+
+impl BaseMapConfig {
+    /// Image used for the gather map
+    pub fn gather_image(&self) -> &str {
+        self.gray_image.as_deref().unwrap_or(&self.image)
+    }
+}
+
+fn default_maps() -> BTreeMap<String, BaseMapConfig> {
+    let mut maps = BTreeMap::new();
+    maps.insert(
+        String::from("main"),
+        BaseMapConfig {
+            image: String::from("./resources/main-map.png"),
+            gray_image: Some(String::from("./resources/main-map-gray.png")),
+            image_url: None,
+            projection: Projection::default(),
+        },
+    );
+    maps
+}
+
 /// Function for initially loading and parsing the config file
 ///
 /// This function should only be called once
@@ -102,7 +315,15 @@ pub fn read_config() -> Config {
         Ok(data) => {
             // parse the config file
             match toml::from_slice::<Config>(&data) {
-                Ok(config) => config,
+                Ok(config) => {
+                    let radius = config.gather.cluster_radius;
+                    if radius.is_nan() || radius <= 0.0 {
+                        error!("failed parsing the config file:");
+                        error!("gather.cluster_radius has to be greater than 0");
+                        panic!();
+                    }
+                    config
+                }
                 Err(why) => {
                     error!("failed parsing the config file:");
                     error!("{}", why);
@@ -0,0 +1,197 @@
+//! Module for encoding rendered images for upload
+//!
+//! Images are encoded in the format chosen for the request and shrunk until they fit in the upload
+//! limit of the server they are sent to.
+
+use std::io::Cursor;
+
+use image::imageops::{self, FilterType};
+use image::{DynamicImage, ImageBuffer, ImageOutputFormat, Rgba};
+use serde::{Deserialize, Serialize};
+
+use crate::{Context, Error};
+
+/// Lowest quality lossy images are reduced to before they get scaled down instead
+const MIN_QUALITY: f32 = 50.0;
+
+/// How much the quality is lowered at a time
+const QUALITY_STEP: f32 = 10.0;
+
+/// How much images are scaled down at a time
+const SCALE_STEP: f64 = 0.75;
+
+/// Images aren't scaled down past this width
+const MIN_WIDTH: u32 = 200;
+
+/// Upload limit of servers without enough boosts for a higher one
+pub const DEFAULT_UPLOAD_LIMIT: usize = 8 * 1024 * 1024;
+
+/// Format images are uploaded in
+#[derive(
+    poise::SlashChoiceParameter, Serialize, Deserialize, Clone, Copy, PartialEq, Eq, Hash, Debug,
+)]
+#[serde(rename_all = "snake_case")]
+pub enum ImageFormat {
+    #[name = "png"]
+    Png,
+    #[name = "webp (lossless)"]
+    WebpLossless,
+    #[name = "webp"]
+    Webp,
+}
+
+impl Default for ImageFormat {
+    fn default() -> Self {
+        Self::Webp
+    }
+}
+
+impl ImageFormat {
+    /// File extension of the format
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::WebpLossless | Self::Webp => "webp",
+        }
+    }
+
+    /// Human readable name of the format
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Png => "png",
+            Self::WebpLossless => "lossless webp",
+            Self::Webp => "webp",
+        }
+    }
+}
+
+/// An encoded image and the settings that were used for it
+pub struct Encoded {
+    pub data: Vec<u8>,
+    pub format: ImageFormat,
+    /// Quality of lossy images
+    pub quality: Option<f32>,
+    /// Size of the image compared to the rendered image
+    pub scale: f64,
+}
+
+impl Encoded {
+    /// File name for the image with the right extension
+    pub fn filename(&self, stem: &str) -> String {
+        format!("{}.{}", stem, self.format.extension())
+    }
+
+    /// Short description of the settings for embed footers
+    pub fn describe(&self) -> String {
+        let mut out = String::from(self.format.name());
+        if let Some(quality) = self.quality {
+            out.push_str(&format!(" q{}", quality));
+        }
+        if self.scale < 1.0 {
+            out.push_str(&format!(" at {}%", (self.scale * 100.0).round()));
+        }
+        out
+    }
+}
+
+/// Encode an image so that it fits in the size limit
+///
+/// Lossy images first have their quality lowered, after that the image is scaled down until it
+/// fits.
+pub fn encode(
+    img: ImageBuffer<Rgba<u8>, Vec<u8>>,
+    format: ImageFormat,
+    quality: f32,
+    limit: usize,
+) -> Result<Encoded, Error> {
+    let (width, height) = img.dimensions();
+    let mut quality = quality.clamp(0.0, 100.0);
+    let mut scale = 1.0;
+    let mut current = img;
+
+    loop {
+        let data = encode_once(&current, format, quality)?;
+
+        if data.len() <= limit || current.width() <= MIN_WIDTH {
+            if data.len() > limit {
+                return Err("The image is too big to upload".into());
+            }
+            return Ok(Encoded {
+                data,
+                format,
+                quality: (format == ImageFormat::Webp).then(|| quality),
+                scale,
+            });
+        }
+
+        if format == ImageFormat::Webp && quality > MIN_QUALITY {
+            quality = (quality - QUALITY_STEP).max(MIN_QUALITY);
+        } else {
+            scale *= SCALE_STEP;
+            current = imageops::resize(
+                &current,
+                (width as f64 * scale) as u32,
+                (height as f64 * scale) as u32,
+                FilterType::Triangle,
+            );
+        }
+    }
+}
+
+/// Encode an image without any size limits
+fn encode_once(
+    img: &ImageBuffer<Rgba<u8>, Vec<u8>>,
+    format: ImageFormat,
+    quality: f32,
+) -> Result<Vec<u8>, Error> {
+    match format {
+        ImageFormat::Png => {
+            let mut out = Cursor::new(Vec::new());
+            DynamicImage::ImageRgba8(img.clone()).write_to(&mut out, ImageOutputFormat::Png)?;
+            Ok(out.into_inner())
+        }
+        ImageFormat::WebpLossless | ImageFormat::Webp => {
+            let img = DynamicImage::ImageRgba8(img.clone());
+            let encoder = webp::Encoder::from_image(&img)?;
+            let encoded = if format == ImageFormat::Webp {
+                encoder.encode(quality)
+            } else {
+                encoder.encode_lossless()
+            };
+            Ok((*encoded).to_vec())
+        }
+    }
+}
+
+/// Largest file that can be uploaded where the command was used
+///
+/// Servers with enough boosts have a higher limit than the default 8MB.
+pub fn upload_limit(ctx: Context<'_>) -> usize {
+    use poise::serenity_prelude::PremiumTier;
+
+    const MB: usize = 1024 * 1024;
+
+    match ctx.guild().map(|g| g.premium_tier) {
+        Some(PremiumTier::Tier2) => 50 * MB,
+        Some(PremiumTier::Tier3) => 100 * MB,
+        _ => DEFAULT_UPLOAD_LIMIT,
+    }
+}
+
+/// Pick the format for a request
+///
+/// The format given with the command is used first, then the server's preference and lastly the
+/// default from the config.
+pub async fn pick_format(ctx: Context<'_>, requested: Option<ImageFormat>) -> ImageFormat {
+    if let Some(format) = requested {
+        return format;
+    }
+
+    if let Some(guild) = ctx.guild_id() {
+        if let Some(format) = ctx.data().settings.get(guild.0).await.image_format {
+            return format;
+        }
+    }
+
+    ctx.data().config.image.format
+}
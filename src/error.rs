@@ -18,6 +18,13 @@ pub async fn error_handler(error: poise::FrameworkError<'_, Data, Error>) {
                     &format!("{}", err),
                 )
                 .await;
+            } else if error.downcast_ref::<crate::render::QueueFull>().is_some() {
+                create_error_msg(
+                    ctx,
+                    "Too busy",
+                    "Too many images are being rendered right now, try again in a bit",
+                )
+                .await;
             } else {
                 create_error_msg(
                     ctx,
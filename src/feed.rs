@@ -0,0 +1,340 @@
+//! Module for the territory takeover feed
+//!
+//! A background task polls the territory list, compares it to the previous snapshot and posts the
+//! changes to every channel that has subscribed to them. The subscriptions and the last snapshot
+//! are saved to disk so nothing is posted twice across restarts.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use poise::serenity_prelude::{ChannelId, Http};
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tracing::{error, info, warn};
+
+use crate::commands::map::get_map;
+use crate::config::FeedConfig;
+use crate::history::HistoryDb;
+use crate::util::format_duration;
+use crate::wynn::world::Territories;
+use crate::Error;
+
+/// Discord message length limit
+const MAX_MESSAGE_LEN: usize = 2000;
+
+/// Who held a territory and since when
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Holder {
+    pub guild: String,
+    pub prefix: String,
+    pub acquired: String,
+}
+
+/// Snapshot of who holds every territory
+pub type Snapshot = HashMap<String, Holder>;
+
+/// A channel subscribed to the feed
+#[derive(Serialize, Deserialize, Clone)]
+pub struct Subscription {
+    /// Names or prefixes of guilds to post changes for, empty means every guild
+    pub guilds: Vec<String>,
+}
+
+/// Everything that gets saved to disk
+#[derive(Serialize, Deserialize, Default)]
+pub struct FeedState {
+    /// Subscriptions keyed by channel id
+    pub subscriptions: HashMap<u64, Subscription>,
+    /// The last snapshot that changes were posted for
+    pub snapshot: Option<Snapshot>,
+}
+
+/// A territory changing hands
+///
+/// The old and new holder are the same guild if the territory was lost and taken back between two
+/// polls.
+pub struct Takeover {
+    pub territory: String,
+    pub new: Holder,
+    pub old: Holder,
+}
+
+impl Takeover {
+    /// Checks if either of the guilds involved match the filter
+    fn involves(&self, guild: &str) -> bool {
+        let guild = guild.trim();
+        [&self.new, &self.old]
+            .iter()
+            .any(|h| h.guild.eq_ignore_ascii_case(guild) || h.prefix.eq_ignore_ascii_case(guild))
+    }
+
+    /// Checks if the guild that held the territory took it back
+    pub fn is_retake(&self) -> bool {
+        self.new.guild == self.old.guild
+    }
+
+    /// Unix timestamp of when the territory was taken
+    pub fn time(&self) -> i64 {
+        Holder::parse_time(&self.new.acquired)
+            .map(|t| t.timestamp())
+            .unwrap_or_else(|| chrono::Utc::now().timestamp())
+    }
+
+    /// Message line describing the takeover
+    fn describe(&self) -> String {
+        let held = match (
+            Holder::parse_time(&self.old.acquired),
+            Holder::parse_time(&self.new.acquired),
+        ) {
+            (Some(old), Some(new)) => format!(
+                " (held for {})",
+                format_duration((new - old).num_seconds().max(0))
+            ),
+            _ => String::new(),
+        };
+
+        if self.is_retake() {
+            format!(
+                "**{}** [{}] retook **{}**{}",
+                self.new.guild, self.new.prefix, self.territory, held
+            )
+        } else {
+            format!(
+                "**{}** [{}] took **{}** from **{}** [{}]{}",
+                self.new.guild,
+                self.new.prefix,
+                self.territory,
+                self.old.guild,
+                self.old.prefix,
+                held
+            )
+        }
+    }
+}
+
+impl Holder {
+    fn parse_time(time: &str) -> Option<chrono::NaiveDateTime> {
+        chrono::NaiveDateTime::parse_from_str(time, "%Y-%m-%d %H:%M:%S").ok()
+    }
+}
+
+/// Persistent store for the feed state
+pub struct FeedStore {
+    path: String,
+    state: Mutex<FeedState>,
+}
+
+impl FeedStore {
+    /// Load the store from the given file or start with an empty one
+    pub async fn load(path: &str) -> Self {
+        let state = match fs::read(path).await {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(state) => state,
+                Err(why) => {
+                    error!(
+                        "Failed to parse the feed state, starting from scratch: {}",
+                        why
+                    );
+                    FeedState::default()
+                }
+            },
+            Err(_) => {
+                info!("No feed state found at {}, starting from scratch", path);
+                FeedState::default()
+            }
+        };
+
+        Self {
+            path: path.to_string(),
+            state: Mutex::new(state),
+        }
+    }
+
+    /// Write the state to disk
+    async fn save(&self, state: &FeedState) -> Result<(), Error> {
+        if let Some(dir) = std::path::Path::new(&self.path).parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        // write to a temporary file first so a crash can't leave a half written file behind
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, serde_json::to_vec(state)?).await?;
+        fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+
+    /// Subscribe a channel to the feed, replacing any previous subscription
+    pub async fn subscribe(&self, channel: u64, guilds: Vec<String>) -> Result<(), Error> {
+        let mut state = self.state.lock().await;
+        state.subscriptions.insert(channel, Subscription { guilds });
+        self.save(&state).await
+    }
+
+    /// Unsubscribe a channel, returns false if the channel wasn't subscribed
+    pub async fn unsubscribe(&self, channel: u64) -> Result<bool, Error> {
+        let mut state = self.state.lock().await;
+        let removed = state.subscriptions.remove(&channel).is_some();
+        if removed {
+            self.save(&state).await?;
+        }
+        Ok(removed)
+    }
+
+    /// Get the subscription of a channel
+    pub async fn subscription(&self, channel: u64) -> Option<Subscription> {
+        self.state.lock().await.subscriptions.get(&channel).cloned()
+    }
+}
+
+/// Turn the territory list into a snapshot
+pub fn snapshot(terrs: &Territories) -> Snapshot {
+    terrs
+        .territories
+        .values()
+        .map(|t| {
+            (
+                t.territory.clone(),
+                Holder {
+                    guild: t.guild.clone(),
+                    prefix: t.guildPrefix.clone(),
+                    acquired: t.acquired.clone(),
+                },
+            )
+        })
+        .collect()
+}
+
+/// Find all territories that changed hands between two snapshots
+///
+/// A new acquire time with the same guild means the territory was lost and retaken in between.
+pub fn diff(old: &Snapshot, new: &Snapshot) -> Vec<Takeover> {
+    let mut out: Vec<Takeover> = new
+        .iter()
+        .filter_map(|(name, holder)| {
+            let prev = old.get(name)?;
+            if prev.guild != holder.guild || prev.acquired != holder.acquired {
+                Some(Takeover {
+                    territory: name.clone(),
+                    new: holder.clone(),
+                    old: prev.clone(),
+                })
+            } else {
+                None
+            }
+        })
+        .collect();
+
+    out.sort_by(|a, b| a.new.acquired.cmp(&b.new.acquired));
+    out
+}
+
+/// Start polling the territory list in the background
+///
+/// Changes are recorded to the war history if it is available and only posted to the subscribed
+/// channels if the feed is enabled.
+pub fn start(
+    http: Arc<Http>,
+    store: Arc<FeedStore>,
+    history: Option<HistoryDb>,
+    config: &FeedConfig,
+) {
+    let interval = Duration::from_secs(config.poll_interval.max(30));
+    let post = config.enabled;
+
+    tokio::spawn(async move {
+        let mut timer = tokio::time::interval(interval);
+        loop {
+            timer.tick().await;
+            if let Err(why) = poll(&http, &store, history.as_ref(), post).await {
+                warn!("Polling the territory feed failed: {}", why);
+            }
+        }
+    });
+}
+
+/// Fetch the current territories, record them and post any changes if `post` is set
+async fn poll(
+    http: &Http,
+    store: &FeedStore,
+    history: Option<&HistoryDb>,
+    post: bool,
+) -> Result<(), Error> {
+    let terrs = get_map().await?;
+    let new = snapshot(&terrs);
+
+    let mut state = store.state.lock().await;
+
+    let changes = match &state.snapshot {
+        Some(old) => diff(old, &new),
+        None => Vec::new(),
+    };
+
+    // keep a snapshot in the history whenever the map changes, the first one is always recorded
+    if let Some(history) = history {
+        if state.snapshot.is_none() || !changes.is_empty() {
+            let now = chrono::Utc::now().timestamp();
+            if let Err(why) = history.record_snapshot(now, &new).await {
+                error!("Failed to record a territory snapshot: {}", why);
+            }
+        }
+    }
+
+    // save the snapshot before posting so a crash doesn't cause the same changes to be posted again
+    state.snapshot = Some(new);
+    store.save(&state).await?;
+
+    if changes.is_empty() {
+        return Ok(());
+    }
+
+    info!("{} territories changed hands", changes.len());
+
+    if let Some(history) = history {
+        if let Err(why) = history.record(&changes).await {
+            error!("Failed to record territory changes: {}", why);
+        }
+    }
+
+    if !post {
+        return Ok(());
+    }
+
+    let subscriptions = state.subscriptions.clone();
+    drop(state);
+
+    for (channel, sub) in subscriptions {
+        let lines: Vec<String> = changes
+            .iter()
+            .filter(|c| sub.guilds.is_empty() || sub.guilds.iter().any(|g| c.involves(g)))
+            .map(|c| c.describe())
+            .collect();
+
+        for msg in chunk_lines(&lines) {
+            if let Err(why) = ChannelId(channel).say(http, msg).await {
+                warn!("Failed to post the territory feed to {}: {}", channel, why);
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Join lines into messages that fit within discord's length limit
+fn chunk_lines(lines: &[String]) -> Vec<String> {
+    let mut out = Vec::new();
+    let mut current = String::new();
+
+    for line in lines {
+        if !current.is_empty() && current.len() + line.len() + 1 > MAX_MESSAGE_LEN {
+            out.push(std::mem::take(&mut current));
+        }
+        current.push_str(line);
+        current.push('\n');
+    }
+    if !current.is_empty() {
+        out.push(current);
+    }
+
+    out
+}
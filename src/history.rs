@@ -0,0 +1,283 @@
+//! Module for the persistent territory war history
+//!
+//! Every territory change seen by the feed poller is recorded in an sqlite database so it can be
+//! queried later. Whenever something changes a snapshot of all territory holders is stored as well
+//! so the state of the map at any recorded time can be recreated.
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use rusqlite::{params, Connection};
+
+use crate::feed::{Snapshot, Takeover};
+use crate::Error;
+
+/// A single recorded ownership change
+pub struct Change {
+    pub territory: String,
+    /// Unix timestamp of when the territory was taken
+    pub time: i64,
+    pub new_guild: String,
+    pub new_prefix: String,
+    pub old_guild: String,
+    pub old_prefix: String,
+}
+
+/// Summary of a guild's wars over some time window
+pub struct GuildHistory {
+    pub captures: i64,
+    pub losses: i64,
+    /// Territories with the most changes involving the guild and the number of changes
+    pub contested: Vec<(String, i64)>,
+    /// The latest changes involving the guild
+    pub recent: Vec<Change>,
+}
+
+/// Handle to the history database
+#[derive(Clone)]
+pub struct HistoryDb {
+    conn: Arc<Mutex<Connection>>,
+}
+
+impl HistoryDb {
+    /// Open the database and create the tables if needed
+    pub fn open(path: &str) -> Result<Self, Error> {
+        if let Some(dir) = std::path::Path::new(path).parent() {
+            std::fs::create_dir_all(dir)?;
+        }
+
+        let conn = Connection::open(path)?;
+        conn.execute_batch(
+            "CREATE TABLE IF NOT EXISTS changes (
+                id INTEGER PRIMARY KEY,
+                territory TEXT NOT NULL,
+                time INTEGER NOT NULL,
+                new_guild TEXT NOT NULL,
+                new_prefix TEXT NOT NULL,
+                old_guild TEXT NOT NULL,
+                old_prefix TEXT NOT NULL,
+                UNIQUE (territory, time, new_guild)
+            );
+            CREATE INDEX IF NOT EXISTS changes_time ON changes (time);
+            CREATE TABLE IF NOT EXISTS snapshots (
+                time INTEGER PRIMARY KEY,
+                data TEXT NOT NULL
+            );",
+        )?;
+
+        Ok(Self {
+            conn: Arc::new(Mutex::new(conn)),
+        })
+    }
+
+    /// Run a closure with the connection on the blocking thread pool
+    async fn with_conn<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce(&mut Connection) -> Result<T, rusqlite::Error> + Send + 'static,
+    {
+        let conn = self.conn.clone();
+        let res = tokio::task::spawn_blocking(move || {
+            let mut conn = conn.lock().unwrap();
+            f(&mut conn)
+        })
+        .await??;
+        Ok(res)
+    }
+
+    /// Record territory changes, changes that are already recorded are ignored
+    pub async fn record(&self, changes: &[Takeover]) -> Result<(), Error> {
+        let changes: Vec<Change> = changes
+            .iter()
+            .map(|c| Change {
+                territory: c.territory.clone(),
+                time: c.time(),
+                new_guild: c.new.guild.clone(),
+                new_prefix: c.new.prefix.clone(),
+                old_guild: c.old.guild.clone(),
+                old_prefix: c.old.prefix.clone(),
+            })
+            .collect();
+
+        self.with_conn(move |conn| {
+            let tx = conn.transaction()?;
+            {
+                let mut stmt = tx.prepare(
+                    "INSERT OR IGNORE INTO changes
+                    (territory, time, new_guild, new_prefix, old_guild, old_prefix)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6)",
+                )?;
+                for c in &changes {
+                    stmt.execute(params![
+                        c.territory,
+                        c.time,
+                        c.new_guild,
+                        c.new_prefix,
+                        c.old_guild,
+                        c.old_prefix
+                    ])?;
+                }
+            }
+            tx.commit()
+        })
+        .await
+    }
+
+    /// Record the holders of all territories at the given unix timestamp
+    pub async fn record_snapshot(&self, time: i64, snapshot: &Snapshot) -> Result<(), Error> {
+        let data = serde_json::to_string(snapshot)?;
+
+        self.with_conn(move |conn| {
+            conn.execute(
+                "INSERT OR REPLACE INTO snapshots (time, data) VALUES (?1, ?2)",
+                params![time, data],
+            )?;
+            Ok(())
+        })
+        .await
+    }
+
+    /// Get up to `max` snapshots to show the map between two unix timestamps
+    ///
+    /// Every snapshot in the range is returned together with the last one before `from` if there are
+    /// few enough of them, otherwise the range is split evenly and the latest snapshot at or before
+    /// each point is used. The returned times are the times the snapshots are shown at.
+    pub async fn frames(
+        &self,
+        from: i64,
+        to: i64,
+        max: usize,
+    ) -> Result<Vec<(i64, Snapshot)>, Error> {
+        let rows: Vec<(i64, i64, String)> = self
+            .with_conn(move |conn| {
+                let count: i64 = conn.query_row(
+                    "SELECT COUNT(*) FROM snapshots WHERE time > ?1 AND time <= ?2",
+                    params![from, to],
+                    |r| r.get(0),
+                )?;
+
+                if count < max as i64 {
+                    return conn
+                        .prepare(
+                            "SELECT time, data FROM snapshots
+                            WHERE time >= (SELECT COALESCE(MAX(time), ?1) FROM snapshots WHERE time <= ?1)
+                            AND time <= ?2 ORDER BY time",
+                        )?
+                        .query_map(params![from, to], |r| {
+                            let time: i64 = r.get(0)?;
+                            Ok((time.max(from), time, r.get(1)?))
+                        })?
+                        .collect();
+                }
+
+                // the latest snapshot at or before the time, or the first one if there is none
+                let mut stmt = conn.prepare(
+                    "SELECT time, data FROM snapshots WHERE time = COALESCE(
+                        (SELECT MAX(time) FROM snapshots WHERE time <= ?1),
+                        (SELECT MIN(time) FROM snapshots)
+                    )",
+                )?;
+                let step = (to - from) / (max as i64 - 1);
+                let mut out = Vec::new();
+                for i in 0..max as i64 {
+                    let time = from + step * i;
+                    let mut rows = stmt.query(params![time])?;
+                    if let Some(row) = rows.next()? {
+                        out.push((time, row.get(0)?, row.get(1)?));
+                    }
+                }
+                Ok(out)
+            })
+            .await?;
+
+        // consecutive frames often show the same snapshot so only parse it once
+        let mut out: Vec<(i64, Snapshot)> = Vec::new();
+        let mut last: Option<i64> = None;
+        for (time, snap_time, data) in rows {
+            let snapshot = match out.last() {
+                Some((_, prev)) if last == Some(snap_time) => prev.clone(),
+                _ => serde_json::from_str(&data)?,
+            };
+            last = Some(snap_time);
+            out.push((time, snapshot));
+        }
+        Ok(out)
+    }
+
+    /// Count how many times every territory changed hands since the given unix timestamp
+    ///
+    /// Territories that didn't change are left out.
+    pub async fn change_counts(&self, since: i64) -> Result<HashMap<String, i64>, Error> {
+        self.with_conn(move |conn| {
+            conn.prepare(
+                "SELECT territory, COUNT(*) FROM changes WHERE time >= ?1 GROUP BY territory",
+            )?
+            .query_map(params![since], |r| Ok((r.get(0)?, r.get(1)?)))?
+            .collect()
+        })
+        .await
+    }
+
+    /// Get the war history of a guild since the given unix timestamp
+    ///
+    /// The guild can be given as either its name or prefix.
+    pub async fn guild_history(&self, guild: &str, since: i64) -> Result<GuildHistory, Error> {
+        let guild = guild.trim().to_string();
+
+        self.with_conn(move |conn| {
+            let captures = conn.query_row(
+                "SELECT COUNT(*) FROM changes WHERE time >= ?2
+                AND (new_guild = ?1 COLLATE NOCASE OR new_prefix = ?1 COLLATE NOCASE)",
+                params![guild, since],
+                |r| r.get(0),
+            )?;
+            let losses = conn.query_row(
+                "SELECT COUNT(*) FROM changes WHERE time >= ?2
+                AND (old_guild = ?1 COLLATE NOCASE OR old_prefix = ?1 COLLATE NOCASE)",
+                params![guild, since],
+                |r| r.get(0),
+            )?;
+
+            let contested = conn
+                .prepare(
+                    "SELECT territory, COUNT(*) AS n FROM changes WHERE time >= ?2
+                    AND (new_guild = ?1 COLLATE NOCASE OR new_prefix = ?1 COLLATE NOCASE
+                    OR old_guild = ?1 COLLATE NOCASE OR old_prefix = ?1 COLLATE NOCASE)
+                    GROUP BY territory ORDER BY n DESC, territory LIMIT 5",
+                )?
+                .query_map(params![guild, since], |r| Ok((r.get(0)?, r.get(1)?)))?
+                .collect::<Result<_, _>>()?;
+
+            let recent = conn
+                .prepare(
+                    "SELECT territory, time, new_guild, new_prefix, old_guild, old_prefix
+                    FROM changes WHERE time >= ?2
+                    AND (new_guild = ?1 COLLATE NOCASE OR new_prefix = ?1 COLLATE NOCASE
+                    OR old_guild = ?1 COLLATE NOCASE OR old_prefix = ?1 COLLATE NOCASE)
+                    ORDER BY time DESC LIMIT 10",
+                )?
+                .query_map(params![guild, since], row_to_change)?
+                .collect::<Result<_, _>>()?;
+
+            Ok(GuildHistory {
+                captures,
+                losses,
+                contested,
+                recent,
+            })
+        })
+        .await
+    }
+}
+
+/// Read a change from a row of `territory, time, new_guild, new_prefix, old_guild, old_prefix`
+fn row_to_change(r: &rusqlite::Row) -> Result<Change, rusqlite::Error> {
+    Ok(Change {
+        territory: r.get(0)?,
+        time: r.get(1)?,
+        new_guild: r.get(2)?,
+        new_prefix: r.get(3)?,
+        old_guild: r.get(4)?,
+        old_prefix: r.get(5)?,
+    })
+}
@@ -1,13 +1,29 @@
+mod basemap;
+mod codec;
 mod commands;
 mod config;
+mod encoder;
 mod error;
+mod feed;
 mod help;
+mod history;
+mod palette;
+mod render;
+mod settings;
+mod svg;
+mod util;
 mod wynn;
 
 use cached::proc_macro::once;
-use commands::{gather, id, map, up};
+use commands::{gather, id, map, territory, up};
 use config::Config;
+use feed::FeedStore;
+use history::HistoryDb;
 use poise::serenity_prelude::{self as serenity, ComponentType, Interaction, Event};
+use render::{RenderCache, RenderPool};
+use settings::SettingsStore;
+
+use std::sync::Arc;
 
 use tracing::{error, info, log::warn, Level};
 
@@ -16,6 +32,16 @@ pub const BOT_VERSION: &str = env!("CARGO_PKG_VERSION");
 
 pub struct Data {
     config: Config,
+    /// Subscriptions to the territory takeover feed
+    feed: Arc<FeedStore>,
+    /// Territory war history, None if disabled or the database failed to open
+    history: Option<HistoryDb>,
+    /// Recently rendered maps
+    render_cache: RenderCache,
+    /// Threads that images are rendered on
+    render_pool: RenderPool,
+    /// Per server settings
+    settings: SettingsStore,
 }
 
 pub type Error = Box<dyn std::error::Error + Send + Sync>;
@@ -26,7 +52,7 @@ async fn event_listener(
     ctx: &serenity::Context,
     event: &Event,
     _framework: &poise::Framework<Data, Error>,
-    _user_data: &Data,
+    user_data: &Data,
 ) -> Result<(), Error> {
     match event {
         Event::Ready(event) => {
@@ -41,6 +67,14 @@ async fn event_listener(
                         "update_sp" => {
                             crate::commands::up::sp_interact_handler(ctx, &msg, &intr).await
                         }
+                        id if id.starts_with(commands::guilds::BUTTON_PREFIX) => {
+                            commands::guilds::guilds_interact_handler(ctx, user_data, &msg, &intr)
+                                .await
+                        }
+                        id if id.starts_with(commands::mapview::BUTTON_PREFIX) => {
+                            commands::mapview::map_interact_handler(ctx, user_data, &msg, &intr)
+                                .await
+                        }
                         _ => {
                             warn!("Button with id `{}` pressed but there is no handler for a button with that id", intr.data.custom_id);
                             Ok(())
@@ -81,10 +115,19 @@ async fn main() {
     let options = poise::FrameworkOptions {
         commands: vec![
             map::map(),
+            territory::territory(),
+            commands::guilds::guilds(),
+            commands::whereis::whereis(),
+            commands::feed::feed(),
+            commands::warhistory::warhistory(),
+            commands::timelapse::maptimelapse(),
+            commands::mapheat::mapheat(),
+            commands::imageformat::imageformat(),
             up::up(),
             up::sp(),
             id::id(),
             id::maxid(),
+            id::unid(),
             gather::gather(),
             help::help(),
         ],
@@ -146,8 +189,44 @@ async fn main() {
                     shard_manager.lock().await.shutdown_all().await;
                 });
 
+                // open the war history database
+                let history = if config.history.enabled {
+                    match HistoryDb::open(&config.history.database) {
+                        Ok(db) => Some(db),
+                        Err(why) => {
+                            error!("Failed to open the war history database: {}", why);
+                            None
+                        }
+                    }
+                } else {
+                    None
+                };
+
+                // start posting territory takeovers, the history is recorded by the same poller
+                let feed = Arc::new(FeedStore::load(&config.feed.state_file).await);
+                if config.feed.enabled || history.is_some() {
+                    feed::start(
+                        ctx.http.clone(),
+                        feed.clone(),
+                        history.clone(),
+                        &config.feed,
+                    );
+                }
+
+                let render_cache = RenderCache::new(config.image.render_cache_size * 1024 * 1024);
+                let render_pool =
+                    RenderPool::new(config.image.render_threads, config.image.render_queue);
+                let settings = SettingsStore::load(&config.settings.file).await;
+
                 // Initialize the data struct
-                Ok(Data { config })
+                Ok(Data {
+                    config,
+                    feed,
+                    history,
+                    render_cache,
+                    render_pool,
+                    settings,
+                })
             })
         });
 
@@ -184,3 +263,12 @@ pub fn gen_embed_footer(e: &mut serenity::CreateEmbed, name: &str) {
     });
     e.timestamp(chrono::Utc::now().to_rfc3339());
 }
+
+/// function to generate the embed footer with a note after the bot version
+pub fn gen_embed_footer_note(e: &mut serenity::CreateEmbed, name: &str, note: &str) {
+    e.footer(|f| {
+        f.text(format!("{} {} • {}", name, BOT_VERSION, note));
+        f
+    });
+    e.timestamp(chrono::Utc::now().to_rfc3339());
+}
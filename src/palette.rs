@@ -0,0 +1,167 @@
+//! Module for picking the colours guilds are drawn with
+//!
+//! Guilds without a colour of their own get one from a hash of their name. Hashed colours can end
+//! up looking almost the same, so guilds next to each other on the map get their colours re-picked
+//! until they can be told apart.
+
+use std::collections::{HashMap, HashSet};
+
+use crc32fast::Hasher;
+
+use crate::wynn::world::{Territories, Territory};
+use crate::Error;
+
+/// Colour of every guild on the map keyed by the guild name
+pub type GuildColors = HashMap<String, (u8, u8, u8)>;
+
+/// Smallest perceptual distance between the colours of neighbouring guilds
+const MIN_DISTANCE: f64 = 25.0;
+
+/// How many colours are tried for a guild before settling for the most distinct one
+const MAX_ATTEMPTS: u32 = 16;
+
+/// Territories this many blocks apart still count as neighbours
+const ADJACENCY_TOLERANCE: f64 = 5.0;
+
+/// Pick the colour of every guild holding territories
+///
+/// Guilds that have set a colour always keep it. The rest are handled from the biggest guild to
+/// the smallest so the result is the same no matter the order of the territory list.
+pub fn guild_colors(terrs: &Territories) -> Result<GuildColors, Error> {
+    let mut colors = GuildColors::new();
+    let mut sizes: HashMap<&str, usize> = HashMap::new();
+
+    for terr in terrs.territories.values() {
+        *sizes.entry(&terr.guild).or_default() += 1;
+        if let Some(col) = own_color(terr)? {
+            colors.insert(terr.guild.clone(), col);
+        }
+    }
+
+    let mut remaining: Vec<(&str, usize)> = sizes
+        .into_iter()
+        .filter(|(g, _)| !colors.contains_key(*g))
+        .collect();
+    remaining.sort_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+
+    let neighbours = neighbours(terrs);
+
+    for (guild, _) in remaining {
+        let taken: Vec<(u8, u8, u8)> = neighbours
+            .get(guild)
+            .into_iter()
+            .flatten()
+            .filter_map(|n| colors.get(*n).copied())
+            .collect();
+
+        let mut best = (fallback_color(guild, 0), f64::MIN);
+        for attempt in 0..MAX_ATTEMPTS {
+            let col = fallback_color(guild, attempt);
+            let distance = taken
+                .iter()
+                .map(|t| delta_e(col, *t))
+                .fold(f64::MAX, f64::min);
+
+            if distance > best.1 {
+                best = (col, distance);
+            }
+            if distance >= MIN_DISTANCE {
+                break;
+            }
+        }
+
+        colors.insert(guild.to_string(), best.0);
+    }
+
+    Ok(colors)
+}
+
+/// The colour a guild has set for itself
+fn own_color(terr: &Territory) -> Result<Option<(u8, u8, u8)>, Error> {
+    match &terr.guildColor {
+        Some(col) if !col.is_empty() => {
+            let col = hex::decode(col.trim_start_matches('#'))?;
+            if col.len() < 3 {
+                return Err(format!("Invalid colour for guild {}", terr.guild).into());
+            }
+            Ok(Some((col[0], col[1], col[2])))
+        }
+        _ => Ok(None),
+    }
+}
+
+/// Colour from a hash of the guild's name
+///
+/// Every attempt gives a different colour, the first one is the same as what has always been used.
+fn fallback_color(name: &str, attempt: u32) -> (u8, u8, u8) {
+    let mut hasher = Hasher::new();
+    hasher.update(name.as_bytes());
+    if attempt > 0 {
+        hasher.update(&attempt.to_le_bytes());
+    }
+    let hash = hasher.finalize().to_be_bytes();
+
+    (hash[1], hash[2], hash[3])
+}
+
+/// Find the guilds holding territories next to each other
+fn neighbours(terrs: &Territories) -> HashMap<&str, HashSet<&str>> {
+    let terrs: Vec<&Territory> = terrs.territories.values().collect();
+    let mut out: HashMap<&str, HashSet<&str>> = HashMap::new();
+
+    for (i, a) in terrs.iter().enumerate() {
+        for b in &terrs[i + 1..] {
+            if a.guild == b.guild {
+                continue;
+            }
+
+            let (a_loc, b_loc) = (&a.location, &b.location);
+            let touching = a_loc.min_x() <= b_loc.max_x() + ADJACENCY_TOLERANCE
+                && b_loc.min_x() <= a_loc.max_x() + ADJACENCY_TOLERANCE
+                && a_loc.min_z() <= b_loc.max_z() + ADJACENCY_TOLERANCE
+                && b_loc.min_z() <= a_loc.max_z() + ADJACENCY_TOLERANCE;
+
+            if touching {
+                out.entry(&a.guild).or_default().insert(&b.guild);
+                out.entry(&b.guild).or_default().insert(&a.guild);
+            }
+        }
+    }
+
+    out
+}
+
+/// Perceptual distance between two colours (CIE76)
+fn delta_e(a: (u8, u8, u8), b: (u8, u8, u8)) -> f64 {
+    let (a, b) = (to_lab(a), to_lab(b));
+    ((a.0 - b.0).powi(2) + (a.1 - b.1).powi(2) + (a.2 - b.2).powi(2)).sqrt()
+}
+
+/// Convert an sRGB colour into the CIELAB colour space
+fn to_lab(col: (u8, u8, u8)) -> (f64, f64, f64) {
+    let linear = |c: u8| {
+        let c = c as f64 / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    let (r, g, b) = (linear(col.0), linear(col.1), linear(col.2));
+
+    // xyz relative to the D65 white point
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+
+    let f = |t: f64| {
+        if t > 0.008856 {
+            t.cbrt()
+        } else {
+            7.787 * t + 16.0 / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+
+    (116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz))
+}
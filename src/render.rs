@@ -0,0 +1,246 @@
+//! Module for rendering images and caching the results
+//!
+//! Rendering and encoding a full map takes a while, so it is done on a small pool of dedicated
+//! threads instead of the async runtime and the encoded results are kept around keyed by a hash of
+//! everything that went into them. Requests for an image that is already being rendered wait for
+//! that render instead of starting another one.
+
+use std::collections::HashMap;
+use std::fmt;
+use std::future::Future;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Instant;
+
+use tokio::sync::{oneshot, OnceCell};
+use tracing::warn;
+
+use crate::encoder::Encoded;
+use crate::{Context, Error};
+
+/// A render waiting for a thread
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Error returned when too many renders are already waiting
+#[derive(Debug)]
+pub struct QueueFull;
+
+impl fmt::Display for QueueFull {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "too many images are being rendered")
+    }
+}
+
+impl std::error::Error for QueueFull {}
+
+/// Pool of threads that images are rendered on
+pub struct RenderPool {
+    sender: Mutex<mpsc::Sender<Job>>,
+    threads: usize,
+    max_pending: usize,
+    /// Renders that are running or waiting for a thread
+    pending: Arc<AtomicUsize>,
+}
+
+impl RenderPool {
+    /// Start a pool with the given number of threads
+    ///
+    /// At most `max_queue` renders can wait for a thread at once, renders past that are rejected.
+    pub fn new(threads: usize, max_queue: usize) -> Self {
+        let threads = threads.max(1);
+        let (sender, receiver) = mpsc::channel::<Job>();
+        let receiver = Arc::new(Mutex::new(receiver));
+
+        for i in 0..threads {
+            let receiver = receiver.clone();
+            thread::Builder::new()
+                .name(format!("render-{}", i))
+                .spawn(move || loop {
+                    // the lock is only held while waiting for a job
+                    let job = receiver.lock().unwrap().recv();
+                    match job {
+                        Ok(job) => job(),
+                        Err(_) => break,
+                    }
+                })
+                .expect("Failed to start a render thread");
+        }
+
+        Self {
+            sender: Mutex::new(sender),
+            threads,
+            max_pending: threads + max_queue,
+            pending: Arc::new(AtomicUsize::new(0)),
+        }
+    }
+
+    /// Number of renders a new render would have to wait for
+    pub fn ahead(&self) -> usize {
+        let pending = self.pending.load(Ordering::SeqCst);
+        if pending >= self.threads {
+            pending
+        } else {
+            0
+        }
+    }
+
+    /// Run a function on the pool and wait for the result
+    pub async fn run<T, F>(&self, f: F) -> Result<T, Error>
+    where
+        T: Send + 'static,
+        F: FnOnce() -> Result<T, Error> + Send + 'static,
+    {
+        // reserve a spot in the queue
+        if self.pending.fetch_add(1, Ordering::SeqCst) >= self.max_pending {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err(QueueFull.into());
+        }
+
+        let (tx, rx) = oneshot::channel();
+        let pending = self.pending.clone();
+        let job: Job = Box::new(move || {
+            // a panicking render shouldn't take the thread down with it
+            let result = panic::catch_unwind(AssertUnwindSafe(f))
+                .unwrap_or_else(|_| Err("Rendering the image panicked".into()));
+            pending.fetch_sub(1, Ordering::SeqCst);
+            let _ = tx.send(result);
+        });
+
+        if self.sender.lock().unwrap().send(job).is_err() {
+            self.pending.fetch_sub(1, Ordering::SeqCst);
+            return Err("The render threads have stopped".into());
+        }
+
+        rx.await?
+    }
+}
+
+/// Render an image on the pool and tell the user how many renders are ahead of theirs
+///
+/// The message about the queue is removed again once the render is done.
+pub async fn render<T, F>(ctx: Context<'_>, f: F) -> Result<T, Error>
+where
+    T: Send + 'static,
+    F: FnOnce() -> Result<T, Error> + Send + 'static,
+{
+    let pool = &ctx.data().render_pool;
+
+    let ahead = pool.ahead();
+    let status = if ahead > 0 {
+        ctx.say(format!("Rendering, {} ahead of you", ahead))
+            .await?
+    } else {
+        None
+    };
+
+    let result = pool.run(f).await;
+
+    // the result goes out as its own message so the status line would only be left behind
+    if let Some(status) = status {
+        let deleted = match status.message().await {
+            Ok(msg) => msg.delete(ctx.discord()).await,
+            Err(why) => Err(why),
+        };
+        if let Err(why) = deleted {
+            warn!("Failed to delete the render status message: {}", why);
+        }
+    }
+
+    result
+}
+
+/// A cached image and when it was last used
+struct Entry {
+    data: Arc<OnceCell<Arc<Encoded>>>,
+    last_used: Instant,
+}
+
+/// Size limited cache of encoded images
+///
+/// The least recently used images are dropped once the images take up more than the limit.
+pub struct RenderCache {
+    max_bytes: usize,
+    entries: Mutex<HashMap<u64, Entry>>,
+}
+
+impl RenderCache {
+    /// Create a cache that holds at most `max_bytes` of image data
+    pub fn new(max_bytes: usize) -> Self {
+        Self {
+            max_bytes,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Get the image for the key or render it with the given function
+    pub async fn get_or_render<F, Fut>(&self, key: u64, render: F) -> Result<Arc<Encoded>, Error>
+    where
+        F: FnOnce() -> Fut,
+        Fut: Future<Output = Result<Encoded, Error>>,
+    {
+        if self.max_bytes == 0 {
+            return Ok(Arc::new(render().await?));
+        }
+
+        let cell = {
+            let mut entries = self.entries.lock().unwrap();
+            let entry = entries.entry(key).or_insert_with(|| Entry {
+                data: Arc::new(OnceCell::new()),
+                last_used: Instant::now(),
+            });
+            entry.last_used = Instant::now();
+            entry.data.clone()
+        };
+
+        let mut rendered = false;
+        let result = cell
+            .get_or_try_init(|| async {
+                rendered = true;
+                Ok::<_, Error>(Arc::new(render().await?))
+            })
+            .await
+            .map(Arc::clone);
+
+        match &result {
+            Ok(_) if rendered => self.evict(),
+            Ok(_) => {}
+            // forget failed renders unless someone else is already trying again
+            Err(_) => {
+                let mut entries = self.entries.lock().unwrap();
+                if let Some(e) = entries.get(&key) {
+                    if !e.data.initialized() && Arc::strong_count(&e.data) <= 2 {
+                        entries.remove(&key);
+                    }
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Drop the least recently used images until the cache fits in its limit
+    fn evict(&self) {
+        let mut entries = self.entries.lock().unwrap();
+
+        let mut used: usize = entries
+            .values()
+            .filter_map(|e| e.data.get())
+            .map(|d| d.data.len())
+            .sum();
+
+        while used > self.max_bytes {
+            let oldest = entries
+                .iter()
+                .filter(|(_, e)| e.data.initialized())
+                .min_by_key(|(_, e)| e.last_used)
+                .map(|(k, _)| *k);
+
+            match oldest.and_then(|k| entries.remove(&k)) {
+                Some(e) => used -= e.data.get().map_or(0, |d| d.data.len()),
+                None => break,
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+//! Module for per server settings
+//!
+//! Settings are kept in memory and written to a json file whenever they change.
+
+use std::collections::HashMap;
+
+use serde::{Deserialize, Serialize};
+use tokio::fs;
+use tokio::sync::Mutex;
+use tracing::{error, info};
+
+use crate::encoder::ImageFormat;
+use crate::Error;
+
+/// Settings of a single server
+#[derive(Serialize, Deserialize, Clone, Default)]
+pub struct GuildSettings {
+    /// Format images are uploaded in, the config default is used if unset
+    pub image_format: Option<ImageFormat>,
+}
+
+/// Persistent store for the server settings
+pub struct SettingsStore {
+    path: String,
+    guilds: Mutex<HashMap<u64, GuildSettings>>,
+}
+
+impl SettingsStore {
+    /// Load the settings from the given file or start with no settings
+    pub async fn load(path: &str) -> Self {
+        let guilds = match fs::read(path).await {
+            Ok(data) => match serde_json::from_slice(&data) {
+                Ok(guilds) => guilds,
+                Err(why) => {
+                    error!(
+                        "Failed to parse the server settings, starting from scratch: {}",
+                        why
+                    );
+                    HashMap::new()
+                }
+            },
+            Err(_) => {
+                info!(
+                    "No server settings found at {}, starting from scratch",
+                    path
+                );
+                HashMap::new()
+            }
+        };
+
+        Self {
+            path: path.to_string(),
+            guilds: Mutex::new(guilds),
+        }
+    }
+
+    /// Get the settings of a server
+    pub async fn get(&self, guild: u64) -> GuildSettings {
+        self.guilds
+            .lock()
+            .await
+            .get(&guild)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    /// Change the settings of a server and save them
+    pub async fn update<F>(&self, guild: u64, f: F) -> Result<(), Error>
+    where
+        F: FnOnce(&mut GuildSettings),
+    {
+        let mut guilds = self.guilds.lock().await;
+        f(guilds.entry(guild).or_default());
+
+        if let Some(dir) = std::path::Path::new(&self.path).parent() {
+            fs::create_dir_all(dir).await?;
+        }
+        // write to a temporary file first so a crash can't leave a half written file behind
+        let tmp = format!("{}.tmp", self.path);
+        fs::write(&tmp, serde_json::to_vec(&*guilds)?).await?;
+        fs::rename(&tmp, &self.path).await?;
+        Ok(())
+    }
+}
@@ -0,0 +1,203 @@
+//! Module for exporting the territory map as an svg document
+//!
+//! The document uses the same projection as the rendered maps, so its coordinates are pixels on
+//! the base map image and the territories line up with it. Every territory gets a `<rect>` and
+//! a `<text>` so the map can be restyled or edited by hand.
+
+use std::fmt::Write;
+use std::path::Path;
+
+use crate::basemap::load_image;
+use crate::commands::map::{
+    crop_bounds, crop_zoom, desaturate, route_color, trading_routes, MapOptions, Region,
+    MAX_LABEL_SIZE,
+};
+use crate::config::BaseMapConfig;
+use crate::palette::guild_colors;
+use crate::wynn::world::{Territories, Territory};
+use crate::Error;
+
+/// Approximate width of a bold character compared to the font size
+const CHAR_WIDTH: f64 = 0.65;
+
+/// Create an svg document of the territory map
+///
+/// The base map is linked if it has a public url and embedded otherwise. Regions are cropped with
+/// the view box so the whole map is still there when the document is edited.
+pub fn render_svg(
+    terrs: &Territories,
+    base: &BaseMapConfig,
+    options: &MapOptions,
+    region: Option<Region>,
+    zoom: Option<f64>,
+) -> Result<String, Error> {
+    let proj = &base.projection;
+    let colors = guild_colors(terrs)?;
+
+    let (img_width, img_height) = load_image(&base.image)?.dimensions();
+    let (x, y, width, height) = match region {
+        Some(region) => crop_bounds((img_width, img_height), proj, region),
+        None => (0, 0, img_width, img_height),
+    };
+    let zoom = match region {
+        Some(_) => crop_zoom(zoom, width, height),
+        None => 1.0,
+    };
+
+    let href = match &base.image_url {
+        Some(url) => url.clone(),
+        None => format!(
+            "data:{};base64,{}",
+            mime_type(&base.image),
+            base64::encode(std::fs::read(&base.image)?)
+        ),
+    };
+
+    let mut out = String::new();
+    writeln!(
+        out,
+        r#"<svg xmlns="http://www.w3.org/2000/svg" xmlns:xlink="http://www.w3.org/1999/xlink" width="{}" height="{}" viewBox="{} {} {} {}">"#,
+        (width as f64 * zoom).round(),
+        (height as f64 * zoom).round(),
+        x,
+        y,
+        width,
+        height
+    )?;
+    writeln!(
+        out,
+        r#"<image x="0" y="0" width="{}" height="{}" xlink:href="{}"/>"#,
+        img_width,
+        img_height,
+        escape(&href)
+    )?;
+
+    // sorted so the same territories always give the same document
+    let mut sorted: Vec<&Territory> = terrs.territories.values().collect();
+    sorted.sort_by(|a, b| a.territory.cmp(&b.territory));
+
+    writeln!(out, r#"<g id="territories" stroke-width="1">"#)?;
+    for terr in &sorted {
+        let (x, y, width, height) = territory_rect(base, terr);
+
+        let col = colors[&terr.guild];
+        let col = match &options.highlight {
+            Some(guild) if !terr.held_by(guild) => desaturate(col),
+            _ => col,
+        };
+        let col = format!("#{:02x}{:02x}{:02x}", col.0, col.1, col.2);
+
+        writeln!(
+            out,
+            r#"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" fill-opacity="0.5" stroke="{}"><title>{} - {} [{}]</title></rect>"#,
+            x,
+            y,
+            width,
+            height,
+            col,
+            col,
+            escape(&terr.territory),
+            escape(&terr.guild),
+            escape(&terr.guildPrefix)
+        )?;
+    }
+    writeln!(out, "</g>")?;
+
+    if let Some(mode) = options.routes {
+        writeln!(out, r#"<g id="routes" stroke-width="1">"#)?;
+        for (terr, other) in trading_routes(terrs) {
+            let col = route_color(mode, terr, other);
+            let (x1, y1) = proj.to_pixel(terr.location.center());
+            let (x2, y2) = proj.to_pixel(other.location.center());
+
+            writeln!(
+                out,
+                r##"<line x1="{:.1}" y1="{:.1}" x2="{:.1}" y2="{:.1}" stroke="#{:02x}{:02x}{:02x}" stroke-opacity="{:.2}"/>"##,
+                x1,
+                y1,
+                x2,
+                y2,
+                col.0[0],
+                col.0[1],
+                col.0[2],
+                col.0[3] as f64 / 255.0
+            )?;
+        }
+        writeln!(out, "</g>")?;
+    }
+
+    // labels don't take the mouse so the territory names still show up when hovering them
+    writeln!(
+        out,
+        r##"<g id="labels" font-family="Roboto, sans-serif" font-weight="bold" text-anchor="middle" dominant-baseline="central" fill="#ffffff" stroke="#000000" paint-order="stroke" pointer-events="none">"##
+    )?;
+    for terr in &sorted {
+        if terr.guildPrefix.is_empty() {
+            continue;
+        }
+
+        let (x, y, width, height) = territory_rect(base, terr);
+        let font_size = (height * 0.6)
+            .min(width / (terr.guildPrefix.chars().count() as f64 * CHAR_WIDTH))
+            .min(MAX_LABEL_SIZE as f64);
+
+        writeln!(
+            out,
+            r#"<text x="{:.1}" y="{:.1}" font-size="{:.1}" stroke-width="{:.1}">{}</text>"#,
+            x + width / 2.0,
+            y + height / 2.0,
+            font_size,
+            font_size / 8.0,
+            escape(&terr.guildPrefix)
+        )?;
+    }
+    writeln!(out, "</g>")?;
+
+    writeln!(out, "</svg>")?;
+
+    Ok(out)
+}
+
+/// Position and size of a territory on the base map as `(x, y, width, height)`
+fn territory_rect(base: &BaseMapConfig, terr: &Territory) -> (f64, f64, f64, f64) {
+    let proj = &base.projection;
+    let loc = &terr.location;
+    (
+        proj.x(loc.min_x()),
+        proj.z(loc.min_z()),
+        proj.length(loc.max_x() - loc.min_x()),
+        proj.length(loc.max_z() - loc.min_z()),
+    )
+}
+
+/// Mime type of an image going by its file extension
+fn mime_type(path: &str) -> &'static str {
+    let ext = Path::new(path)
+        .extension()
+        .and_then(|e| e.to_str())
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match ext.as_str() {
+        "jpg" | "jpeg" => "image/jpeg",
+        "webp" => "image/webp",
+        "gif" => "image/gif",
+        _ => "image/png",
+    }
+}
+
+/// Escape text for use in xml
+fn escape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&apos;"),
+            _ => out.push(c),
+        }
+    }
+    out
+}
@@ -0,0 +1,16 @@
+//! Small helpers shared by the commands and the background tasks
+
+/// Formats a duration in seconds as days, hours and minutes
+pub fn format_duration(secs: i64) -> String {
+    let days = secs / 86400;
+    let hours = (secs % 86400) / 3600;
+    let minutes = (secs % 3600) / 60;
+
+    if days > 0 {
+        format!("{}d {}h {}m", days, hours, minutes)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
@@ -18,13 +18,18 @@ pub mod color {
     pub const FABLED_ITEM: Color = Color::from_rgb(255, 85, 85);
     pub const MYTHIC_ITEM: Color = Color::from_rgb(170, 0, 170);
     pub const SET_ITEM: Color = Color::from_rgb(40, 150, 24);
+    pub const CRAFTED_ITEM: Color = Color::from_rgb(0, 170, 170);
 }
 
 /// Module containing structs for holding world information such as territories
 pub mod world {
     use std::collections::HashMap;
 
+    use chrono::{DateTime, NaiveDateTime, Utc};
+    use once_cell::sync::OnceCell;
     use serde::Deserialize;
+    use tokio::fs;
+    use tracing::{info, warn};
 
     /// Struct for the wynntils territories api
     #[derive(Deserialize, Clone)]
@@ -40,8 +45,102 @@ pub mod world {
         pub guildPrefix: String,
         pub guildColor: Option<String>,
         pub acquired: String,
-        // There is additional data we aren't capturing
         pub location: TerritoryLocation,
+        /// Names of the territories this one has trading routes to
+        ///
+        /// The api doesn't give these so they are filled in from [`get_trading_routes`]
+        #[serde(default)]
+        pub tradingRoutes: Vec<String>,
+    }
+
+    impl Territory {
+        /// Checks if the territory is held by the guild with the given name or prefix
+        pub fn held_by(&self, guild: &str) -> bool {
+            let guild = guild.trim();
+            self.guild.eq_ignore_ascii_case(guild) || self.guildPrefix.eq_ignore_ascii_case(guild)
+        }
+
+        /// Parses the time when the territory was acquired
+        ///
+        /// The api gives this as a utc time without any timezone information
+        pub fn acquired_time(&self) -> Option<DateTime<Utc>> {
+            NaiveDateTime::parse_from_str(&self.acquired, "%Y-%m-%d %H:%M:%S")
+                .ok()
+                .map(|t| DateTime::from_utc(t, Utc))
+        }
+    }
+
+    /// Static for the trading routes so they only have to be loaded once
+    static TRADING_ROUTES: OnceCell<HashMap<String, Vec<String>>> = OnceCell::new();
+
+    /// Local territory info file, used instead of the download if it exists
+    const TRADING_ROUTES_FILE: &str = "./resources/territories.json";
+
+    /// Community territory info file listing the trading routes of every territory
+    const TRADING_ROUTES_URL: &str =
+        "https://raw.githubusercontent.com/jakematt123/Wynncraft-Territory-Info/main/territories.json";
+
+    /// Entry of the territory info file, we only care about the trading routes
+    #[derive(Deserialize)]
+    struct TerritoryInfo {
+        #[serde(rename = "Trading Routes", default)]
+        routes: Vec<String>,
+    }
+
+    /// Gets the trading routes between territories
+    ///
+    /// The routes come from the community territory info file where every territory has a list of
+    /// `Trading Routes`. A copy in `./resources/territories.json` is used if there is one, otherwise
+    /// the file is downloaded. Failed loads aren't remembered so they are tried again next time.
+    pub async fn get_trading_routes() -> Option<&'static HashMap<String, Vec<String>>> {
+        if let Some(routes) = TRADING_ROUTES.get() {
+            return Some(routes);
+        }
+
+        match load_trading_routes().await {
+            Ok(routes) => {
+                // another load may have finished first, both have the same data
+                let _ = TRADING_ROUTES.set(routes);
+                TRADING_ROUTES.get()
+            }
+            Err(why) => {
+                warn!("Failed to load the trading routes: {}", why);
+                None
+            }
+        }
+    }
+
+    async fn load_trading_routes() -> Result<HashMap<String, Vec<String>>, crate::Error> {
+        let data = match fs::read(TRADING_ROUTES_FILE).await {
+            Ok(data) => data,
+            Err(_) => {
+                info!("Downloading trading routes from {}", TRADING_ROUTES_URL);
+                crate::get_reqwest_client()?
+                    .get(TRADING_ROUTES_URL)
+                    .send()
+                    .await?
+                    .error_for_status()?
+                    .bytes()
+                    .await?
+                    .to_vec()
+            }
+        };
+
+        let info: HashMap<String, TerritoryInfo> = serde_json::from_slice(&data)?;
+        Ok(info.into_iter().map(|(k, v)| (k, v.routes)).collect())
+    }
+
+    impl Territories {
+        /// Fill in the trading routes of territories that don't have them
+        pub fn fill_trading_routes(&mut self, routes: &HashMap<String, Vec<String>>) {
+            for terr in self.territories.values_mut() {
+                if terr.tradingRoutes.is_empty() {
+                    if let Some(r) = routes.get(&terr.territory) {
+                        terr.tradingRoutes = r.clone();
+                    }
+                }
+            }
+        }
     }
 
     /// Struct representing the location of a territory
@@ -54,6 +153,41 @@ pub mod world {
         pub endX: f64,
         pub endZ: f64,
     }
+
+    impl TerritoryLocation {
+        /// Smallest x coordinate of the territory
+        pub fn min_x(&self) -> f64 {
+            self.startX.min(self.endX)
+        }
+
+        /// Largest x coordinate of the territory
+        pub fn max_x(&self) -> f64 {
+            self.startX.max(self.endX)
+        }
+
+        /// Smallest z coordinate of the territory
+        pub fn min_z(&self) -> f64 {
+            self.startZ.min(self.endZ)
+        }
+
+        /// Largest z coordinate of the territory
+        pub fn max_z(&self) -> f64 {
+            self.startZ.max(self.endZ)
+        }
+
+        /// Centre point of the territory as (x, z)
+        pub fn center(&self) -> (f64, f64) {
+            (
+                (self.startX + self.endX) / 2.0,
+                (self.startZ + self.endZ) / 2.0,
+            )
+        }
+
+        /// Checks if a point is inside of the territory, the edges count as inside
+        pub fn contains(&self, x: f64, z: f64) -> bool {
+            (self.min_x()..=self.max_x()).contains(&x) && (self.min_z()..=self.max_z()).contains(&z)
+        }
+    }
 }
 
 /// Item information and data
@@ -94,6 +228,26 @@ pub mod items {
         NECKLACE,
     }
 
+    impl Type {
+        /// Get the name of the item type
+        pub fn name(&self) -> &str {
+            match self {
+                Type::SPEAR => "Spear",
+                Type::WAND => "Wand",
+                Type::BOW => "Bow",
+                Type::DAGGER => "Dagger",
+                Type::RELIK => "Relik",
+                Type::HELMET => "Helmet",
+                Type::CHESTPLATE => "Chestplate",
+                Type::LEGGINGS => "Leggings",
+                Type::BOOTS => "Boots",
+                Type::RING => "Ring",
+                Type::BRACELET => "Bracelet",
+                Type::NECKLACE => "Necklace",
+            }
+        }
+    }
+
     /// all current wynncraft identifications
     #[derive(Debug, Deserialize, PartialEq, Eq, PartialOrd, Ord, Clone, Copy)]
     pub enum Identification {
@@ -155,6 +309,33 @@ pub mod items {
     }
 
     impl Identification {
+        /// Get the type that the value of this identification has
+        pub fn status_type(&self) -> StatusType {
+            match self {
+                Identification::rawStrength
+                | Identification::rawDexterity
+                | Identification::rawIntelligence
+                | Identification::rawDefence
+                | Identification::rawAgility
+                | Identification::rawMainAttackNeutralDamage
+                | Identification::rawNeutralSpellDamage
+                | Identification::rawSpellDamage
+                | Identification::rawHealth
+                | Identification::rawHealthRegen
+                | Identification::rawJumpHeight
+                | Identification::raw1stSpellCost
+                | Identification::raw2ndSpellCost
+                | Identification::raw3rdSpellCost
+                | Identification::raw4thSpellCost => StatusType::INTEGER,
+                Identification::attackSpeed => StatusType::TIER,
+                Identification::lifeSteal
+                | Identification::manaRegen
+                | Identification::manaSteal => StatusType::FOUR_SECONDS,
+                Identification::poison => StatusType::THREE_SECONDS,
+                _ => StatusType::PERCENTAGE,
+            }
+        }
+
         /// Get the name of the identification as a string
         pub fn name(&self) -> &str {
             match self {
@@ -223,6 +404,21 @@ pub mod items {
         SUPER_FAST,
     }
 
+    impl AttackSpeed {
+        /// Get the attack speed formatted as it is shown in game
+        pub fn name(&self) -> &str {
+            match self {
+                AttackSpeed::SUPER_SLOW => "Super Slow",
+                AttackSpeed::VERY_SLOW => "Very Slow",
+                AttackSpeed::SLOW => "Slow Attack",
+                AttackSpeed::NORMAL => "Normal",
+                AttackSpeed::FAST => "Fast",
+                AttackSpeed::VERY_FAST => "Very Fast",
+                AttackSpeed::SUPER_FAST => "Super Fast",
+            }
+        }
+    }
+
     #[derive(Debug, Deserialize, Clone)]
     pub struct ItemList {
         pub items: Vec<Item>,
@@ -283,37 +479,12 @@ pub mod items {
 
         /// Gets the item's type as a String
         pub fn get_type(&self) -> String {
-            match self.itemInfo.r#type {
-                Type::SPEAR => String::from("Spear"),
-                Type::WAND => String::from("Wand"),
-                Type::BOW => String::from("Bow"),
-                Type::DAGGER => String::from("Dagger"),
-                Type::RELIK => String::from("Relik"),
-                Type::HELMET => String::from("Helmet"),
-                Type::CHESTPLATE => String::from("Chestplate"),
-                Type::LEGGINGS => String::from("Leggings"),
-                Type::BOOTS => String::from("Boots"),
-                Type::RING => String::from("Ring"),
-                Type::BRACELET => String::from("Bracelet"),
-                Type::NECKLACE => String::from("Necklace"),
-            }
+            self.itemInfo.r#type.name().to_string()
         }
 
         /// Gets the attack speed of the item and formats it into a speed
         pub fn get_speed(&self) -> Option<String> {
-            if let Some(speed) = self.attackSpeed {
-                match speed {
-                    AttackSpeed::SUPER_SLOW => Some(String::from("Super Slow")),
-                    AttackSpeed::VERY_SLOW => Some(String::from("Very Slow")),
-                    AttackSpeed::SLOW => Some(String::from("Slow Attack")),
-                    AttackSpeed::NORMAL => Some(String::from("Normal")),
-                    AttackSpeed::FAST => Some(String::from("Fast")),
-                    AttackSpeed::VERY_FAST => Some(String::from("Very Fast")),
-                    AttackSpeed::SUPER_FAST => Some(String::from("Super Fast")),
-                }
-            } else {
-                None
-            }
+            self.attackSpeed.map(|speed| speed.name().to_string())
         }
     }
 
@@ -449,6 +620,106 @@ pub mod Gather {
         pub fishing: Vec<GatherSpot>,
     }
 
+    impl GatherSpots {
+        /// All of the spots together with their profession
+        pub fn all(&self) -> impl Iterator<Item = (Profession, &GatherSpot)> {
+            self.woodCutting
+                .iter()
+                .map(|s| (Profession::Woodcutting, s))
+                .chain(self.mining.iter().map(|s| (Profession::Mining, s)))
+                .chain(self.farming.iter().map(|s| (Profession::Farming, s)))
+                .chain(self.fishing.iter().map(|s| (Profession::Fishing, s)))
+        }
+    }
+
+    /// Profession that gathers from a spot
+    #[derive(poise::SlashChoiceParameter, Clone, Copy, PartialEq, Eq, Hash, Debug)]
+    pub enum Profession {
+        #[name = "woodcutting"]
+        Woodcutting,
+        #[name = "mining"]
+        Mining,
+        #[name = "farming"]
+        Farming,
+        #[name = "fishing"]
+        Fishing,
+    }
+
+    impl Profession {
+        /// Name of the profession
+        pub fn name(self) -> &'static str {
+            match self {
+                Self::Woodcutting => "Woodcutting",
+                Self::Mining => "Mining",
+                Self::Farming => "Farming",
+                Self::Fishing => "Fishing",
+            }
+        }
+
+        /// Materials of the profession from the lowest level to the highest
+        ///
+        /// Every material unlocks 10 levels after the previous one, starting at level 1.
+        pub fn materials(self) -> &'static [&'static str] {
+            match self {
+                Self::Woodcutting => &[
+                    "OAK", "BIRCH", "WILLOW", "ACACIA", "SPRUCE", "JUNGLE", "DARK", "LIGHT",
+                    "PINE", "AVO", "SKY", "DERNIC",
+                ],
+                Self::Mining => &[
+                    "COPPER",
+                    "GRANITE",
+                    "GOLD",
+                    "SANDSTONE",
+                    "IRON",
+                    "SILVER",
+                    "COBALT",
+                    "KANDERSTONE",
+                    "DIAMOND",
+                    "MOLTEN",
+                    "VOIDSTONE",
+                    "DERNIC",
+                ],
+                Self::Farming => &[
+                    "WHEAT",
+                    "BARLEY",
+                    "OAT",
+                    "MALT",
+                    "HOPS",
+                    "RYE",
+                    "MILLET",
+                    "DECAYROOTS",
+                    "RICE",
+                    "SORGHUM",
+                    "HEMP",
+                    "DERNIC",
+                ],
+                Self::Fishing => &[
+                    "GUDGEON", "TROUT", "SALMON", "CARP", "ICEFISH", "PIRANHA", "KOI", "GYLIA",
+                    "BASS", "MOLTEN", "STARFISH", "DERNIC",
+                ],
+            }
+        }
+
+        /// Tier of a material starting at 1 for the lowest level one
+        pub fn tier(self, material: &str) -> Option<u8> {
+            let material = normalize_material(material);
+            self.materials()
+                .iter()
+                .position(|m| *m == material)
+                .map(|i| i as u8 + 1)
+        }
+    }
+
+    /// Material name in the form used for comparing them
+    ///
+    /// Names are compared ignoring case, spaces and underscores.
+    pub fn normalize_material(name: &str) -> String {
+        name.chars()
+            .filter(|c| c.is_alphanumeric())
+            .map(|c| c.to_ascii_uppercase())
+            .collect()
+    }
+
     #[derive(Clone, Deserialize)]
     pub struct GatherSpot {
         //pub reliability: i32, // not needed for anything